@@ -16,5 +16,5 @@ fn main() {
 
     let paths = reads.into_iter().map(|v| v.expect("Cannot read file entry").path()).collect::<Vec<_>>();
 
-    e2e_cc::run::<_,StdoutLock>(settings, paths, None);
+    e2e_cc::run::<_,StdoutLock>(settings, paths, e2e_cc::OutputFormat::Sonar, None).expect("Cannot write the coverage report");
 }
@@ -0,0 +1,393 @@
+use crate::statements::{can_end_statement, can_start_statement, tokenize, Token, TokenKind, CONTROL_WORDS};
+use std::collections::BTreeMap;
+
+/// A 0 based line and UTF-16 column in a source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The kind of code that branches
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BranchKind {
+    If,
+    Ternary,
+    /// A chain of `&&`, `||` or `??`
+    Logical,
+    Switch,
+    DefaultParameter,
+}
+
+/// A point where the code takes one of several paths, like an `if` or a ternary
+#[derive(Debug, PartialEq)]
+pub struct BranchPoint {
+    pub kind: BranchKind,
+    pub position: Position,
+    /// Where the code of each branch starts
+    pub arms: Vec<Position>,
+    /// Whether there's one more branch, taken when none of the others are, like the missing
+    /// `else` of an `if`
+    pub implicit_else: bool,
+}
+
+/// Keywords that come before an expression, rather than being part of it
+const EXPRESSION_START_WORDS: &[&str] = &["return", "throw", "case", "yield", "else", "do", "in", "of", "default"];
+
+fn position(token: &Token<'_>) -> Position {
+    Position { line: token.line, column: token.column }
+}
+
+fn is_opener(token: &Token<'_>) -> bool {
+    match token.kind {
+        TokenKind::Punct => matches!(token.text, "(" | "[" | "{"),
+        TokenKind::Literal => token.text.ends_with("${") && !token.text.starts_with(['\'', '"']),
+        TokenKind::Word => false,
+    }
+}
+
+fn is_closer(token: &Token<'_>) -> bool {
+    match token.kind {
+        TokenKind::Punct => matches!(token.text, ")" | "]" | "}"),
+        TokenKind::Literal => token.text.starts_with('}'),
+        TokenKind::Word => false,
+    }
+}
+
+/// Tokens that an operand of `&&`, `||` or `??` can't extend back past
+fn is_expression_boundary(token: &Token<'_>) -> bool {
+    match token.kind {
+        TokenKind::Punct => {
+            matches!(token.text, "," | ";" | "?" | ":" | "=>" | "...")
+                || (token.text.ends_with('=') && !matches!(token.text, "==" | "===" | "!=" | "!==" | "<=" | ">="))
+        }
+        TokenKind::Word => EXPRESSION_START_WORDS.contains(&token.text),
+        TokenKind::Literal => false,
+    }
+}
+
+struct Scanner<'a> {
+    tokens: Vec<Token<'a>>,
+    /// The index of the bracket closing the one opened at each index
+    closes: Vec<Option<usize>>,
+    /// The index of the bracket opening the one closed at each index
+    opens: Vec<Option<usize>>,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(source: &'a str) -> Scanner<'a> {
+        let tokens = tokenize(source);
+        let mut closes = vec![None; tokens.len()];
+        let mut opens = vec![None; tokens.len()];
+        let mut stack = vec![];
+        for (idx, token) in tokens.iter().enumerate() {
+            // The middle of a template literal, `} ... ${`, closes one substitution and opens the next
+            if is_closer(token) {
+                if let Some(open) = stack.pop() {
+                    closes[open] = Some(idx);
+                    opens[idx] = Some(open);
+                }
+            }
+            if is_opener(token) {
+                stack.push(idx);
+            }
+        }
+        Scanner { tokens, closes, opens }
+    }
+
+    fn is_punct(&self, idx: usize, text: &str) -> bool {
+        self.tokens.get(idx).is_some_and(|token| token.is_punct(text))
+    }
+
+    fn is_word(&self, idx: usize, text: &str) -> bool {
+        self.tokens.get(idx).is_some_and(|token| token.is_word(text))
+    }
+
+    /// The index after the token at `idx`, jumping over the brackets it opens
+    fn step(&self, idx: usize) -> usize {
+        let mut idx = idx;
+        while let Some(close) = self.closes[idx] {
+            idx = close;
+        }
+        idx + 1
+    }
+
+    /// The index of the first token of whatever ends at `idx`, jumping back over brackets
+    fn unit_start(&self, idx: usize) -> usize {
+        let mut idx = idx;
+        while let Some(open) = self.opens[idx] {
+            idx = open;
+        }
+        idx
+    }
+
+    /// Whether the statement that `tokens[idx - 1]` is part of ends before `tokens[idx]`, through
+    /// automatic semicolon insertion
+    fn line_ends_statement(&self, idx: usize) -> bool {
+        let token = &self.tokens[idx];
+        token.newline_before && idx > 0 && can_end_statement(&self.tokens[idx - 1]) && can_start_statement(token)
+    }
+
+    /// The index just past the statement starting at `start`
+    fn statement_end(&self, start: usize) -> usize {
+        if self.is_punct(start, "{") {
+            return self.step(start);
+        }
+        let mut idx = start;
+        while idx < self.tokens.len() {
+            let token = &self.tokens[idx];
+            if token.is_punct(";") {
+                return idx + 1;
+            }
+            if idx > start && (token.is_word("else") || is_closer(token) || self.line_ends_statement(idx)) {
+                return idx;
+            }
+            idx = self.step(idx);
+        }
+        idx
+    }
+
+    /// The index of the `:` of the ternary whose `?` is at `idx`
+    fn ternary_colon(&self, idx: usize) -> Option<usize> {
+        let mut nested = 0;
+        let mut idx = idx + 1;
+        while idx < self.tokens.len() {
+            let token = &self.tokens[idx];
+            if token.is_punct(";") || is_closer(token) {
+                return None;
+            }
+            if self.is_ternary(idx) {
+                nested += 1;
+            } else if token.is_punct(":") {
+                if nested == 0 {
+                    return Some(idx);
+                }
+                nested -= 1;
+            }
+            idx = self.step(idx);
+        }
+        None
+    }
+
+    /// Whether the `?` at `idx` is a ternary rather than typescript's optional marker
+    fn is_ternary(&self, idx: usize) -> bool {
+        self.is_punct(idx, "?")
+            && !self.tokens.get(idx + 1).is_some_and(|next| {
+                next.kind == TokenKind::Punct && matches!(next.text, ":" | ")" | "," | "=" | "?")
+            })
+    }
+
+    /// The index of the first token of the operand ending just before `idx`
+    fn operand_start(&self, idx: usize) -> Option<usize> {
+        let mut start = idx;
+        let mut current = idx.checked_sub(1);
+        while let Some(last) = current {
+            let token = &self.tokens[last];
+            if is_opener(token) || is_expression_boundary(token) {
+                break;
+            }
+            if start < idx && self.line_ends_statement(start) {
+                break;
+            }
+            start = self.unit_start(last);
+            current = start.checked_sub(1);
+        }
+        if start < idx {
+            Some(start)
+        } else {
+            None
+        }
+    }
+
+    fn if_statement(&self, idx: usize) -> Option<BranchPoint> {
+        let consequent = self.step(idx + 1);
+        self.tokens.get(consequent)?;
+        let end = self.statement_end(consequent);
+        let mut arms = vec![position(&self.tokens[consequent])];
+        let has_else = self.is_word(end, "else") && end + 1 < self.tokens.len();
+        if has_else {
+            arms.push(position(&self.tokens[end + 1]));
+        }
+        Some(BranchPoint { kind: BranchKind::If, position: position(&self.tokens[idx]), arms, implicit_else: !has_else })
+    }
+
+    fn ternary(&self, idx: usize) -> Option<BranchPoint> {
+        let colon = self.ternary_colon(idx)?;
+        let start = self.operand_start(idx)?;
+        Some(BranchPoint {
+            kind: BranchKind::Ternary,
+            position: position(&self.tokens[start]),
+            arms: vec![position(self.tokens.get(idx + 1)?), position(self.tokens.get(colon + 1)?)],
+            implicit_else: false,
+        })
+    }
+
+    fn switch_statement(&self, idx: usize) -> Option<BranchPoint> {
+        let body = self.step(idx + 1);
+        if !self.is_punct(body, "{") {
+            return None;
+        }
+        let body_end = self.closes[body]?;
+
+        let mut arms = vec![];
+        let mut clause = body + 1;
+        while clause < body_end {
+            let is_clause = self.is_word(clause, "case") || (self.is_word(clause, "default") && self.is_punct(clause + 1, ":"));
+            if is_clause {
+                let mut colon = clause + 1;
+                while colon < body_end && !self.is_punct(colon, ":") {
+                    colon = if self.is_ternary(colon) { self.ternary_colon(colon).map_or(body_end, |end| end + 1) } else { self.step(colon) };
+                }
+                // An empty case falls through to the next one, so it starts at its label
+                let falls_through = colon + 1 >= body_end
+                    || self.is_word(colon + 1, "case")
+                    || (self.is_word(colon + 1, "default") && self.is_punct(colon + 2, ":"));
+                let start = if falls_through { clause } else { colon + 1 };
+                arms.push(position(&self.tokens[start]));
+            }
+            clause = self.step(clause);
+        }
+
+        if arms.is_empty() {
+            None
+        } else {
+            Some(BranchPoint { kind: BranchKind::Switch, position: position(&self.tokens[idx]), arms, implicit_else: false })
+        }
+    }
+
+    /// The parameters with default values in the parameter list opened at `idx`
+    fn default_parameters(&self, idx: usize) -> Vec<BranchPoint> {
+        let close = match self.closes[idx] {
+            Some(close) => close,
+            None => return vec![],
+        };
+        let before_body = self.is_punct(close + 1, "=>") || self.is_punct(close + 1, "{") || self.is_punct(close + 1, ":");
+        let after_control = idx > 0 && self.tokens[idx - 1].kind == TokenKind::Word && CONTROL_WORDS.contains(&self.tokens[idx - 1].text);
+        if !before_body || after_control {
+            return vec![];
+        }
+
+        let mut defaults = vec![];
+        let mut parameter = idx + 1;
+        while parameter < close {
+            if self.is_punct(parameter, "=") && parameter + 1 < close {
+                defaults.push(BranchPoint {
+                    kind: BranchKind::DefaultParameter,
+                    position: position(&self.tokens[parameter - 1]),
+                    arms: vec![position(&self.tokens[parameter + 1])],
+                    implicit_else: false,
+                });
+            }
+            parameter = self.step(parameter);
+        }
+        defaults
+    }
+}
+
+/// Finds the branches in a javascript (or typescript) source the way istanbul does: `if`
+/// statements, ternaries, chains of `&&`, `||` and `??`, `switch` cases and default parameters.
+pub fn branch_points(source: &str) -> Vec<BranchPoint> {
+    let scanner = Scanner::new(source);
+    let tokens = &scanner.tokens;
+
+    let mut points = vec![];
+    // Logical expressions by the index of their first operand
+    let mut logical: BTreeMap<usize, BranchPoint> = BTreeMap::new();
+
+    for (idx, token) in tokens.iter().enumerate() {
+        let after_dot = idx > 0 && (tokens[idx - 1].is_punct(".") || tokens[idx - 1].is_punct("?."));
+        match token.kind {
+            TokenKind::Word if after_dot => {}
+            TokenKind::Word if token.text == "if" && scanner.is_punct(idx + 1, "(") => {
+                points.extend(scanner.if_statement(idx));
+            }
+            TokenKind::Word if token.text == "switch" && scanner.is_punct(idx + 1, "(") => {
+                points.extend(scanner.switch_statement(idx));
+            }
+            TokenKind::Punct if scanner.is_ternary(idx) => {
+                points.extend(scanner.ternary(idx));
+            }
+            TokenKind::Punct if matches!(token.text, "&&" | "||" | "??") => {
+                let (start, operand) = match (scanner.operand_start(idx), tokens.get(idx + 1)) {
+                    (Some(start), Some(operand)) => (start, operand),
+                    _ => continue,
+                };
+                logical
+                    .entry(start)
+                    .or_insert_with(|| BranchPoint {
+                        kind: BranchKind::Logical,
+                        position: position(&tokens[start]),
+                        arms: vec![position(&tokens[start])],
+                        implicit_else: false,
+                    })
+                    .arms
+                    .push(position(operand));
+            }
+            TokenKind::Punct if token.text == "(" => {
+                points.extend(scanner.default_parameters(idx));
+            }
+            _ => {}
+        }
+    }
+
+    points.extend(logical.into_values());
+    points.sort_by_key(|point| point.position);
+    points
+}
+
+#[cfg(test)]
+mod test {
+    use crate::branches::{branch_points, Position};
+
+    /// The line, arm positions and implicit else of a branch point
+    type Summary = (usize, Vec<(usize, usize)>, bool);
+
+    fn summary(source: &str) -> Vec<Summary> {
+        branch_points(source)
+            .into_iter()
+            .map(|point| {
+                let arms = point.arms.iter().map(|Position { line, column }| (*line, *column)).collect();
+                (point.position.line, arms, point.implicit_else)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_if_statements() {
+        let source = "if (a) {\n  b();\n} else if (c)\n  d()\nelse e();\nif (f) g();\n";
+        assert_eq!(
+            summary(source),
+            vec![
+                (0, vec![(0, 7), (2, 7)], false),
+                (2, vec![(3, 2), (4, 5)], false),
+                (5, vec![(5, 7)], true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expressions() {
+        let source = "const a = b ? c(d ? 1 : 2) : e;\nreturn f && g.h || (i ?? j);\n";
+        assert_eq!(
+            summary(source),
+            vec![
+                (0, vec![(0, 14), (0, 29)], false),
+                (0, vec![(0, 20), (0, 24)], false),
+                (1, vec![(1, 7), (1, 12), (1, 19)], false),
+                (1, vec![(1, 20), (1, 25)], false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_switch_and_default_parameters() {
+        let source = "function f(a, b = 2, opt?: string) {\n  switch (a) {\n    case 1:\n    case 2:\n      return b;\n    default:\n      return 0;\n  }\n}\n";
+        assert_eq!(
+            summary(source),
+            vec![
+                (0, vec![(0, 18)], false),
+                (1, vec![(2, 4), (4, 6), (6, 6)], false),
+            ]
+        );
+    }
+}
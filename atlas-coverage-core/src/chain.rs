@@ -0,0 +1,256 @@
+use crate::index_map::{encode_mappings, is_relative, parse_source_map};
+use crate::model::{find_source_mapping_url, SourceMap};
+use crate::settings::Settings;
+use crate::source_map::resolve_source;
+use crate::util;
+use crate::vlq_decode::{MappingData, MappingEntry, SourceEntry};
+use std::error::Error;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+/// How many source maps deep a chain is followed
+const MAX_DEPTH: usize = 10;
+
+/// What a source of the map turned into once the chain is followed
+enum Target {
+    /// Still a source, at this index of the composed map
+    Source(i64),
+    /// Generated itself, with its own mappings (by generated line) into the composed sources
+    Map {
+        lines: Vec<Vec<MappingEntry>>,
+        source_offset: i64,
+        name_offset: i64,
+    },
+}
+
+/// Follows the source maps of the map's own sources (e.g. babel output that maps back to the
+/// typescript it came from), so the mappings point at the files that were originally written
+pub fn compose_source_maps(settings: &Settings, source_map: SourceMap, bundle_path: &str) -> SourceMap {
+    let mut visiting = vec![canonical(bundle_path)];
+    compose(settings, source_map, &mut visiting)
+}
+
+fn canonical(path: &str) -> PathBuf {
+    Path::new(path).canonicalize().unwrap_or_else(|_| PathBuf::from(path))
+}
+
+fn read_source_map(
+    settings: &Settings,
+    source_path: &str,
+    source_mapping_url: &str,
+) -> Option<Result<SourceMap, Box<dyn Error>>> {
+    let source_dir = Path::new(source_path).parent().unwrap_or_else(|| Path::new(""));
+    if let Some(decoded) = util::decode_data_url(source_mapping_url) {
+        let parsed = decoded
+            .and_then(|content| Ok(::serde_json::from_str(&content)?))
+            .and_then(|value| parse_source_map(value, source_dir));
+        return Some(parsed);
+    }
+
+    // Libraries often reference maps they don't ship, those are left alone
+    let map_path = crate::source_map_path(settings, source_path, source_mapping_url);
+    if !map_path.exists() {
+        return None;
+    }
+    let map_dir = map_path.parent().unwrap_or(source_dir);
+    Some(util::deserialize_object(&map_path).and_then(|value| parse_source_map(value, map_dir)))
+}
+
+fn load_inner_map(
+    settings: &Settings,
+    source_map: &SourceMap,
+    idx: usize,
+    source_url: &str,
+    visiting: &mut Vec<PathBuf>,
+) -> Option<SourceMap> {
+    let source_path = resolve_source(settings, source_map, source_url);
+    let content = source_map
+        .sourcesContent
+        .as_ref()
+        .and_then(|contents| contents.get(idx).cloned().flatten())
+        .or_else(|| util::fast_read(&source_path).ok())?;
+    let source_mapping_url = find_source_mapping_url(&content)?;
+
+    let key = canonical(&source_path);
+    if visiting.contains(&key) {
+        eprintln!("Not following the source map of {}, it's part of a cycle", source_path);
+        return None;
+    }
+    if visiting.len() > MAX_DEPTH {
+        eprintln!("Not following the source map of {}, the chain is more than {} maps deep", source_path, MAX_DEPTH);
+        return None;
+    }
+
+    match read_source_map(settings, &source_path, &source_mapping_url)? {
+        Ok(inner_map) => {
+            eprintln!("Following the source map of {}", source_path);
+            visiting.push(key);
+            let inner_map = compose(settings, inner_map, visiting);
+            visiting.pop();
+            Some(inner_map)
+        }
+        Err(err) => {
+            eprintln!("Couldn't deserialize the source map of {}", source_path);
+            eprintln!("{}", err);
+            None
+        }
+    }
+}
+
+/// The segments of the mappings, by generated line
+fn mapping_lines(mappings: &str) -> Vec<Vec<MappingEntry>> {
+    let mut lines: Vec<Vec<MappingEntry>> = vec![];
+    for entry in MappingData::new(mappings.as_bytes()) {
+        let line = entry.gen_line as usize;
+        if lines.len() <= line {
+            lines.resize_with(line + 1, Vec::new);
+        }
+        lines[line].push(entry);
+    }
+    for line in lines.iter_mut() {
+        line.sort_by_key(|entry| entry.gen_column);
+    }
+    lines
+}
+
+/// The segment covering a position, i.e. the last one on the line starting at or before it
+fn lookup(lines: &[Vec<MappingEntry>], line: i64, column: i64) -> Option<&SourceEntry> {
+    let segments = lines.get(line as usize)?;
+    let idx = segments.partition_point(|segment| segment.gen_column <= column);
+    segments.get(idx.checked_sub(1)?)?.source.as_ref()
+}
+
+fn compose(settings: &Settings, source_map: SourceMap, visiting: &mut Vec<PathBuf>) -> SourceMap {
+    let source_urls = source_map.source_urls();
+    let inner_maps = source_urls
+        .iter()
+        .enumerate()
+        .map(|(idx, source_url)| load_inner_map(settings, &source_map, idx, source_url, visiting))
+        .collect::<Vec<_>>();
+    if inner_maps.iter().all(Option::is_none) {
+        return source_map;
+    }
+
+    let outer_contents = source_map.sourcesContent.clone().unwrap_or_default();
+    let mut has_content = source_map.sourcesContent.is_some();
+    let mut sources = vec![];
+    let mut sources_content = vec![];
+    let mut names = source_map.names.clone();
+
+    let targets = source_urls
+        .into_iter()
+        .zip(inner_maps)
+        .enumerate()
+        .map(|(idx, (source_url, inner_map))| match inner_map {
+            None => {
+                sources.push(source_url);
+                sources_content.push(outer_contents.get(idx).cloned().flatten());
+                Target::Source(sources.len() as i64 - 1)
+            }
+            Some(inner_map) => {
+                let (source_offset, name_offset) = (sources.len() as i64, names.len() as i64);
+                // The inner sources are relative to the inner map, so they're pinned down here
+                for inner_url in inner_map.source_urls() {
+                    sources.push(if is_relative(&inner_url) {
+                        util::normalize_path(&inner_map.dir.join(&inner_url)).to_string_lossy().to_string()
+                    } else {
+                        resolve_source(settings, &inner_map, &inner_url)
+                    });
+                }
+                has_content |= inner_map.sourcesContent.is_some();
+                let inner_contents = inner_map.sourcesContent.clone().unwrap_or_default();
+                sources_content.extend(inner_contents.into_iter().chain(iter::repeat(None)).take(inner_map.sources.len()));
+                names.extend(inner_map.names.iter().cloned());
+                Target::Map { lines: mapping_lines(&inner_map.mappings), source_offset, name_offset }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let entries = MappingData::new(source_map.mappings.as_bytes())
+        .map(|entry| MappingEntry {
+            source: entry.source.and_then(|source| match &targets[source.source_idx as usize] {
+                Target::Source(source_idx) => Some(SourceEntry { source_idx: *source_idx, ..source }),
+                Target::Map { lines, source_offset, name_offset } => {
+                    lookup(lines, source.source_line, source.source_col).map(|inner| SourceEntry {
+                        source_idx: inner.source_idx + source_offset,
+                        source_line: inner.source_line,
+                        source_col: inner.source_col,
+                        name_idx: inner.name_idx.map(|name_idx| name_idx + name_offset).or(source.name_idx),
+                    })
+                }
+            }),
+            ..entry
+        })
+        .collect::<Vec<_>>();
+
+    SourceMap {
+        version: 3,
+        file: source_map.file,
+        sourceRoot: None,
+        sources,
+        sourcesContent: if has_content { Some(sources_content) } else { None },
+        names,
+        mappings: encode_mappings(entries),
+        dir: source_map.dir,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::chain::compose_source_maps;
+    use crate::model::SourceMap;
+    use crate::source_map::{process_references, FilePath, SourceType};
+    use crate::test_util;
+    use crate::vlq_decode::MappingData;
+    use std::path::PathBuf;
+
+    fn data_url(source_map: &::serde_json::Value) -> String {
+        format!("data:application/json;base64,{}", ::base64::encode(source_map.to_string()))
+    }
+
+    #[test]
+    fn test_compose_chained_maps() {
+        // build/app.js line 1 column 4 comes from src/app.ts line 3 column 2
+        let inner_map = ::serde_json::json!({
+            "version": 3, "sources": ["../src/app.ts"], "names": [], "mappings": ";IAEE"
+        });
+        let intermediate = format!("var a;\nvar b = 1;\n//# sourceMappingURL={}", data_url(&inner_map));
+        let mut outer_map: SourceMap = ::serde_json::from_value(::serde_json::json!({
+            "version": 3,
+            "sources": ["/project/build/app.js", "/project/src/other.js"],
+            "sourcesContent": [intermediate, "other();"],
+            "names": [],
+            // bundle column 0 is app.js 1:4, column 5 is app.js 0:0 (unmapped in app.ts), column 9 is other.js
+            "mappings": "AACI,KADJ,ICAA"
+        })).unwrap();
+        outer_map.dir = PathBuf::from("/project/dist");
+
+        let composed = compose_source_maps(&test_util::settings("/project/", "/project/dist/"), outer_map, "/project/dist/main.js");
+        assert_eq!(composed.sources, vec!["/project/src/app.ts", "/project/src/other.js"]);
+        assert_eq!(composed.sourcesContent, Some(vec![None, Some("other();".to_owned())]));
+
+        let mappings = MappingData::new(composed.mappings.as_bytes())
+            .map(|v| (v.gen_column, v.source.map(|s| (s.source_idx, s.source_line, s.source_col))))
+            .collect::<Vec<_>>();
+        assert_eq!(mappings, vec![(0, Some((0, 2, 2))), (5, None), (9, Some((1, 0, 0)))]);
+
+        // The chained source isn't on disk, but it's still recognized as a user source
+        let references = process_references(&test_util::settings("/project/", "/project/dist/"), &composed);
+        assert!(matches!(references[0].file_path, FilePath::Unresolved(ref path) if path == "/project/src/app.ts"));
+        assert!(matches!(references[0].source_type, SourceType::User));
+    }
+
+    #[test]
+    fn test_cycles_are_not_followed() {
+        // The source claims to be generated from itself
+        let self_map = ::serde_json::json!({
+            "version": 3, "sources": ["/project/build/app.js"], "names": [], "mappings": "AAAA"
+        });
+        let mut source_map: SourceMap = ::serde_json::from_value(self_map.clone()).unwrap();
+        source_map.sourcesContent = Some(vec![Some(format!("a();\n//# sourceMappingURL={}", data_url(&self_map)))]);
+        source_map.dir = PathBuf::from("/project/dist");
+
+        let composed = compose_source_maps(&test_util::settings("/project/", "/project/dist/"), source_map, "/project/dist/main.js");
+        assert_eq!(composed.sources, vec!["/project/build/app.js"]);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::lines::{count_branches, count_lines, line_rate, ManyCoverage};
+use crate::util::{self, relative_to};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use xml::writer::XmlEvent;
+use xml::EventWriter;
+
+fn package_name(directory: &str, base: &str) -> String {
+    let relative = relative_to(directory, base).trim_matches('/');
+    if relative.is_empty() {
+        "main".to_owned()
+    } else {
+        relative.replace('/', ".")
+    }
+}
+
+impl ManyCoverage {
+    pub fn write_cobertura<W: Write>(&self, writer: W, source_base: &str) -> io::Result<()> {
+        let directories = self.files_by_directory();
+        let (lines_covered, lines_valid) = count_lines(&self.files);
+        let (branches_covered, branches_valid) = count_branches(&self.files);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        let mut xml_writer = EventWriter::new(writer);
+        xml_writer
+            .write(
+                XmlEvent::start_element("coverage")
+                    .attr("line-rate", &format!("{:.4}", line_rate(lines_covered, lines_valid)))
+                    .attr("branch-rate", &format!("{:.4}", line_rate(branches_covered, branches_valid)))
+                    .attr("lines-covered", &format!("{}", lines_covered))
+                    .attr("lines-valid", &format!("{}", lines_valid))
+                    .attr("branches-covered", &format!("{}", branches_covered))
+                    .attr("branches-valid", &format!("{}", branches_valid))
+                    .attr("complexity", "0")
+                    .attr("version", "0.1")
+                    .attr("timestamp", &format!("{}", timestamp)),
+            ).map_err(util::xml_error)?;
+
+        xml_writer.write(XmlEvent::start_element("sources")).map_err(util::xml_error)?;
+        xml_writer.write(XmlEvent::start_element("source")).map_err(util::xml_error)?;
+        xml_writer.write(XmlEvent::characters(source_base)).map_err(util::xml_error)?;
+        xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
+        xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
+
+        xml_writer.write(XmlEvent::start_element("packages")).map_err(util::xml_error)?;
+        for (directory, files) in &directories {
+            let (package_covered, package_valid) = count_lines(files.iter().cloned());
+            let (package_branches_covered, package_branches_valid) = count_branches(files.iter().cloned());
+            xml_writer
+                .write(
+                    XmlEvent::start_element("package")
+                        .attr("name", &package_name(directory, source_base))
+                        .attr("line-rate", &format!("{:.4}", line_rate(package_covered, package_valid)))
+                        .attr("branch-rate", &format!("{:.4}", line_rate(package_branches_covered, package_branches_valid)))
+                        .attr("complexity", "0"),
+                ).map_err(util::xml_error)?;
+            xml_writer.write(XmlEvent::start_element("classes")).map_err(util::xml_error)?;
+            for file in files {
+                let class_name = Path::new(&file.path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| file.path.clone());
+                xml_writer
+                    .write(
+                        XmlEvent::start_element("class")
+                            .attr("name", &class_name)
+                            .attr("filename", relative_to(&file.path, source_base))
+                            .attr("line-rate", &format!("{:.4}", file.line_rate()))
+                            .attr("branch-rate", &format!("{:.4}", line_rate(file.branches_covered(), file.branches_valid())))
+                            .attr("complexity", "0"),
+                    ).map_err(util::xml_error)?;
+                xml_writer.write(XmlEvent::start_element("methods")).map_err(util::xml_error)?;
+                xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
+                xml_writer.write(XmlEvent::start_element("lines")).map_err(util::xml_error)?;
+                let branches = file.branches_by_line();
+                for line in &file.lines {
+                    let number = format!("{}", line.line_number);
+                    let hits = format!("{}", line.hits);
+                    let element = XmlEvent::start_element("line").attr("number", &number).attr("hits", &hits);
+                    let condition_coverage = branches.get(&line.line_number).map(|(covered, valid)| {
+                        format!("{:.0}% ({}/{})", line_rate(*covered, *valid) * 100.0, covered, valid)
+                    });
+                    let element = match condition_coverage {
+                        Some(ref condition_coverage) => {
+                            element.attr("branch", "true").attr("condition-coverage", condition_coverage)
+                        }
+                        None => element.attr("branch", "false"),
+                    };
+                    xml_writer.write(element).map_err(util::xml_error)?;
+                    xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
+                }
+                xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
+                xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
+            }
+            xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
+            xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
+        }
+        xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
+        xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
+
+        xml_writer.into_inner().flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cobertura::package_name;
+    use crate::util::relative_to;
+    use crate::branches::BranchKind;
+    use crate::lines::{BranchCoverage, FileCoverage, LineCoverage, ManyCoverage};
+
+    #[test]
+    fn test_package_name() {
+        assert_eq!(package_name("/project/src/app", "/project/"), "src.app");
+        assert_eq!(package_name("/project", "/project/"), "main");
+        assert_eq!(package_name("/projects/other", "/project/"), "projects.other");
+        assert_eq!(relative_to("/project/src/app/a.js", "/project/"), "src/app/a.js");
+        assert_eq!(relative_to("/elsewhere/a.js", "/project/"), "/elsewhere/a.js");
+    }
+
+    #[test]
+    fn test_write_cobertura() {
+        let many_coverage = ManyCoverage {
+            files: vec![
+                FileCoverage {
+                    path: "/project/src/app/a.js".to_string(),
+                    lines: vec![
+                        LineCoverage { line_number: 1, hits: 3, partial: false },
+                        LineCoverage { line_number: 2, hits: 0, partial: false },
+                    ],
+                    functions: vec![],
+                    branches: vec![BranchCoverage { kind: BranchKind::If, line_number: 1, hits: vec![3, 0] }],
+                    source: None,
+                },
+                FileCoverage {
+                    path: "/project/src/app/b.js".to_string(),
+                    lines: vec![LineCoverage { line_number: 4, hits: 1, partial: false }],
+                    functions: vec![],
+                    branches: vec![],
+                    source: None,
+                },
+                FileCoverage {
+                    path: "/project/src/c.js".to_string(),
+                    lines: vec![LineCoverage { line_number: 1, hits: 0, partial: false }],
+                    functions: vec![],
+                    branches: vec![],
+                    source: None,
+                },
+            ],
+        };
+
+        let mut output = vec![];
+        many_coverage.write_cobertura(&mut output, "/project/").unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains(r#"line-rate="0.5000" branch-rate="0.5000" lines-covered="2" lines-valid="4" branches-covered="1" branches-valid="2""#));
+        assert!(output.contains("<source>/project/</source>"));
+        assert!(output.contains(r#"<package name="src.app" line-rate="0.6667""#));
+        assert!(output.contains(r#"<package name="src" line-rate="0.0000""#));
+        assert!(output.contains(r#"<class name="a.js" filename="src/app/a.js" line-rate="0.5000""#));
+        assert!(output.contains(r#"<line number="1" hits="3" branch="true" condition-coverage="50% (1/2)" />"#));
+        assert!(output.contains(r#"<line number="4" hits="1" branch="false" />"#));
+    }
+}
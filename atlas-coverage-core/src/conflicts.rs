@@ -0,0 +1,156 @@
+use crate::lines::FileCoverage;
+use crate::offsets::split_lines;
+use crate::settings::{ConflictResolution, Settings};
+use crate::util;
+use std::collections::HashSet;
+use std::fs::File;
+
+/// Beyond this many line pairs the changed part of a file isn't diffed, and all of it counts
+/// as changed
+const MAX_DIFF_CELLS: usize = 16_000_000;
+
+/// A source that changed on disk since the source map of a script was built
+#[derive(Debug, Serialize)]
+pub struct Conflict {
+    pub path: String,
+    pub script: String,
+    pub resolution: ConflictResolution,
+}
+
+/// For each line of `old`, the line of `new` with the same text, when the line is unchanged.
+/// Lines are matched with the longest common subsequence of both versions.
+pub fn line_mapping(old: &str, new: &str) -> Vec<Option<usize>> {
+    let old = split_lines(old);
+    let new = split_lines(new);
+    let mut mapping = vec![None; old.len()];
+
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    for (line, mapped) in mapping.iter_mut().enumerate().take(prefix) {
+        *mapped = Some(line);
+    }
+    for offset in 1..=suffix {
+        mapping[old.len() - offset] = Some(new.len() - offset);
+    }
+
+    let old_changed = &old[prefix..old.len() - suffix];
+    let new_changed = &new[prefix..new.len() - suffix];
+    let (rows, columns) = (old_changed.len(), new_changed.len());
+    if rows == 0 || columns == 0 || rows * columns > MAX_DIFF_CELLS {
+        return mapping;
+    }
+
+    // lengths[i][j] is the length of the common subsequence of old_changed[i..] and new_changed[j..]
+    let width = columns + 1;
+    let mut lengths = vec![0u32; (rows + 1) * width];
+    for i in (0..rows).rev() {
+        for j in (0..columns).rev() {
+            lengths[i * width + j] = if old_changed[i] == new_changed[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < rows && j < columns {
+        if old_changed[i] == new_changed[j] {
+            mapping[prefix + i] = Some(prefix + j);
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    mapping
+}
+
+/// Moves the coverage of a source, calculated against its `sourcesContent`, onto the `current`
+/// version on disk. Whatever is on a changed line is dropped.
+pub fn remap_file(file: &mut FileCoverage, current: String) {
+    let original = file.source.take().unwrap_or_default();
+    let mapping = line_mapping(&original, &current);
+    let remap = |line_number: usize| mapping.get(line_number - 1).copied().flatten().map(|line| line + 1);
+
+    file.lines.retain_mut(|line| remap(line.line_number).map(|mapped| line.line_number = mapped).is_some());
+    file.functions.retain_mut(|function| remap(function.line_number).map(|mapped| function.line_number = mapped).is_some());
+    file.branches.retain_mut(|branch| remap(branch.line_number).map(|mapped| branch.line_number = mapped).is_some());
+    file.source = Some(current);
+}
+
+/// Applies the `on_conflict` setting to the coverage of the sources of `script` that changed
+/// since its source map was built, and records the conflicts
+pub fn resolve_conflicts(settings: &Settings, script: &str, conflicting: &HashSet<String>, files: Vec<FileCoverage>, conflicts: &mut Vec<Conflict>) -> Vec<FileCoverage> {
+    let resolution = settings.on_conflict;
+    files
+        .into_iter()
+        .filter_map(|mut file| {
+            if !conflicting.contains(&file.path) {
+                return Some(file);
+            }
+            conflicts.push(Conflict { path: file.path.clone(), script: script.to_owned(), resolution });
+
+            match resolution {
+                ConflictResolution::Skip => None,
+                ConflictResolution::Trust => Some(file),
+                ConflictResolution::Remap => match util::fast_read(&file.path) {
+                    Ok(current) => {
+                        remap_file(&mut file, current);
+                        Some(file)
+                    }
+                    Err(err) => {
+                        eprintln!("Cannot read the source file {} - {}", &file.path, err);
+                        None
+                    }
+                },
+            }
+        })
+        .collect()
+}
+
+/// Writes the conflicts to the `conflict_report` as JSON, if there is one
+pub fn write_report(settings: &Settings, conflicts: &[Conflict]) {
+    if let Some(ref report_path) = settings.conflict_report {
+        let file = File::create(report_path).expect("Could not create the conflict report");
+        ::serde_json::to_writer_pretty(file, conflicts).expect("Could not write the conflict report");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::conflicts::{line_mapping, remap_file};
+    use crate::branches::BranchKind;
+    use crate::lines::{BranchCoverage, FileCoverage, FunctionCoverage, LineCoverage};
+
+    #[test]
+    fn test_line_mapping() {
+        let old = "a\nb\nc\nd\ne\n";
+        let new = "a\nx\nc\nd\ny\nz\ne\n";
+        assert_eq!(line_mapping(old, new), vec![Some(0), None, Some(2), Some(3), Some(6)]);
+        assert_eq!(line_mapping(old, old), vec![Some(0), Some(1), Some(2), Some(3), Some(4)]);
+        assert_eq!(line_mapping("a\nb\n", ""), vec![None, None]);
+        // Line breaks count the way source maps count them
+        assert_eq!(line_mapping("a\rb\u{2028}c\n", "a\r\nx\nb\u{2029}c"), vec![Some(0), Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn test_remap_file() {
+        let mut file = FileCoverage {
+            path: "/src/math.js".to_owned(),
+            lines: vec![(2, 1), (3, 0), (6, 2)].into_iter().map(|(line_number, hits)| LineCoverage { line_number, hits, partial: false }).collect(),
+            functions: vec![FunctionCoverage { name: "add".to_owned(), line_number: 1, calls: 1 }],
+            branches: vec![BranchCoverage { kind: BranchKind::If, line_number: 6, hits: vec![2, 0] }],
+            source: Some("function add(a, b) {\n  log(a);\n  return a + b;\n}\n\nif (x) y();\n".to_owned()),
+        };
+        remap_file(&mut file, "// Adds\nfunction add(a, b) {\n  return a + b;\n}\n\nif (x) y();\n".to_owned());
+
+        let lines = file.lines.iter().map(|line| (line.line_number, line.hits)).collect::<Vec<_>>();
+        assert_eq!(lines, vec![(3, 0), (6, 2)]);
+        assert_eq!(file.functions[0].line_number, 2);
+        assert_eq!(file.branches[0].line_number, 6);
+        assert!(file.source.unwrap().starts_with("// Adds"));
+    }
+}
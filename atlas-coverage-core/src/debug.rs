@@ -54,7 +54,7 @@ fn print_if_has_existing_source_map(settings: &Settings, data: PuppeteerData) {
             let file_refs = references.iter().map(|s| s.file_path.clone()).collect();
             let line_refs = calculate_executable_line_mappings(&source_map, references);
             let file_coverage : Vec<FileCoverage> =
-                calculate_line_coverage(data.ranges, line_refs, file_refs, data.text.as_str());
+                calculate_line_coverage(data.ranges, line_refs, vec![], file_refs, data.text.as_str());
 
             file_coverage.iter().for_each(|fc| {
                 let content = util::fast_read(&fc.path);
@@ -0,0 +1,158 @@
+use crate::lines::FunctionCoverage;
+use crate::model::{FunctionRange, SourceMap};
+use crate::offsets::OffsetIndex;
+use crate::vlq_decode::{MappingData, MappingEntry};
+
+/// The generated code after the identifier at the start of `units`
+fn after_identifier(units: &[u16]) -> String {
+    let text = String::from_utf16_lossy(units);
+    text.trim_start_matches(|c: char| c.is_alphanumeric() || c == '_' || c == '$').trim_start().to_owned()
+}
+
+/// Whether the generated code from the start of a name up to a function makes the name the
+/// function's, like the `f=` of `f=()=>{}` or the `f:` of `{f:function(){}}`
+fn binds_function(between: &[u16]) -> bool {
+    let rest = after_identifier(between);
+    rest.trim_end() == "=" || rest.trim_end() == ":"
+}
+
+/// The original name of a function, from the source map's `names`. That's either a named
+/// segment before the function's parameters, like `add` in `function add(a,b)`, or one that the
+/// function is assigned to.
+fn original_name(source_map: &SourceMap, segments: &[(usize, &MappingEntry)], first: usize, function: &FunctionRange, units: &[u16]) -> Option<String> {
+    let name = |segment: &MappingEntry| {
+        let name_idx = segment.source.as_ref()?.name_idx?;
+        source_map.names.get(name_idx as usize).cloned()
+    };
+
+    for (offset, segment) in &segments[first..] {
+        let before_parameters = units
+            .get(function.start..*offset)
+            .is_some_and(|between| !between.iter().any(|unit| matches!(char::from_u32(*unit as u32), Some('(' | '{' | '='))));
+        if *offset >= function.end || !before_parameters {
+            break;
+        }
+        // The name of the function, rather than that of an arrow function's parameter
+        let names_parameters = units.get(*offset..).is_some_and(|after| after_identifier(after).starts_with('('));
+        if let Some(name) = name(segment).filter(|_| names_parameters) {
+            return Some(name);
+        }
+    }
+
+    let (offset, segment) = segments.get(first.checked_sub(1)?)?;
+    name(segment).filter(|_| units.get(*offset..function.start).is_some_and(binds_function))
+}
+
+/// Maps the functions V8 reported onto the sources of the source map, by where their code starts.
+/// Functions are named after their original name when the source map has it, and otherwise
+/// after the name V8 reported.
+pub fn map_functions(functions: &[FunctionRange], source_map: &SourceMap, minified: &str) -> Vec<Vec<FunctionCoverage>> {
+    let mut by_source: Vec<Vec<FunctionCoverage>> = source_map.sources.iter().map(|_| vec![]).collect();
+    if functions.is_empty() {
+        return by_source;
+    }
+
+    let offsets = OffsetIndex::new(minified);
+    let units = minified.encode_utf16().collect::<Vec<_>>();
+    let entries = MappingData::new(source_map.mappings.as_bytes()).collect::<Vec<_>>();
+    let segments = entries
+        .iter()
+        .filter_map(|entry| Some((offsets.offset(entry.gen_line as usize, entry.gen_column as usize)?, entry)))
+        .collect::<Vec<_>>();
+
+    for function in functions {
+        let first = segments.partition_point(|(offset, _)| *offset < function.start);
+        let declaration = segments[first..]
+            .iter()
+            .take_while(|(offset, _)| *offset < function.end)
+            .find_map(|(_, segment)| segment.source.as_ref());
+        let declaration = match declaration {
+            Some(declaration) => declaration,
+            None => continue,
+        };
+
+        let name = original_name(source_map, &segments, first, function, &units)
+            .or_else(|| Some(function.name.clone()).filter(|name| !name.is_empty()));
+        if let Some(functions) = by_source.get_mut(declaration.source_idx as usize) {
+            functions.push(FunctionCoverage {
+                name: name.unwrap_or_default(),
+                line_number: declaration.source_line as usize + 1,
+                calls: function.count,
+            });
+        }
+    }
+
+    for functions in by_source.iter_mut() {
+        functions.sort_by_key(|function| function.line_number);
+        for (idx, function) in functions.iter_mut().enumerate() {
+            if function.name.is_empty() {
+                function.name = format!("(anonymous_{})", idx);
+            }
+        }
+    }
+
+    by_source
+}
+
+#[cfg(test)]
+mod test {
+    use crate::functions::map_functions;
+    use crate::index_map::encode_mappings;
+    use crate::model::{FunctionRange, SourceMap};
+    use crate::vlq_decode::{MappingEntry, SourceEntry};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_map_functions() {
+        // Minified from
+        //   function add(first, second) {
+        //     return first + second;
+        //   }
+        //
+        //   const twice = x => add(x, x);
+        //   [1].map(function (y) { return y; });
+        let minified = "function a(n,r){return n+r}const b=n=>a(n,n);[1].map(function(n){return n});";
+        let segments: &[(i64, i64, i64, Option<i64>)] = &[
+            (0, 0, 0, None),
+            (9, 0, 9, Some(0)),
+            (11, 0, 13, Some(1)),
+            (13, 0, 20, Some(2)),
+            (16, 1, 2, None),
+            (23, 1, 9, Some(1)),
+            (25, 1, 17, Some(2)),
+            (27, 4, 0, None),
+            (33, 4, 6, Some(3)),
+            (35, 4, 14, Some(4)),
+            (38, 4, 19, Some(0)),
+            (45, 5, 0, None),
+            (53, 5, 8, None),
+            (62, 5, 18, Some(5)),
+            (65, 5, 23, None),
+        ];
+        let entries = segments
+            .iter()
+            .map(|(gen_column, source_line, source_col, name_idx)| MappingEntry {
+                gen_line: 0,
+                gen_column: *gen_column,
+                source: Some(SourceEntry { source_idx: 0, source_line: *source_line, source_col: *source_col, name_idx: *name_idx }),
+            })
+            .collect();
+        let source_map = SourceMap {
+            version: 3,
+            file: None,
+            sourceRoot: None,
+            sources: vec!["math.js".to_owned()],
+            sourcesContent: None,
+            names: vec!["add", "first", "second", "twice", "x", "y"].into_iter().map(String::from).collect(),
+            mappings: encode_mappings(entries),
+            dir: PathBuf::new(),
+        };
+
+        let function = |name: &str, start, end, count| FunctionRange { name: name.to_owned(), start, end, count };
+        let functions = vec![function("a", 0, 27, 2), function("b", 35, 44, 0), function("", 53, 74, 1)];
+
+        let mapped = map_functions(&functions, &source_map, minified);
+        let summary = mapped[0].iter().map(|f| (f.name.as_str(), f.line_number, f.calls)).collect::<Vec<_>>();
+        assert_eq!(summary, vec![("add", 1, 2), ("twice", 5, 0), ("(anonymous_2)", 6, 1)]);
+    }
+}
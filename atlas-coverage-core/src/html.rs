@@ -0,0 +1,323 @@
+use crate::lines::{count_functions, count_lines, line_rate, FileCoverage, LineState, ManyCoverage};
+use crate::offsets::split_lines;
+use crate::util::relative_to;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.4em; }
+a { color: #0b5394; text-decoration: none; }
+a:hover { text-decoration: underline; }
+table { border-collapse: collapse; }
+.summary th, .summary td { padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; text-align: left; }
+.summary tr.directory td { background: #f3f3f3; font-weight: bold; }
+.summary td.file { padding-left: 2em; }
+.bar { width: 120px; height: 0.8em; background: #e06666; display: inline-block; }
+.bar span { height: 100%; background: #6aa84f; display: block; }
+.high { color: #38761d; }
+.medium { color: #b45f06; }
+.low { color: #990000; }
+.source { font-family: monospace; font-size: 0.9em; width: 100%; }
+.source td { padding: 0 0.5em; white-space: pre; vertical-align: top; }
+.source td.line-number, .source td.hits { color: #888; text-align: right; user-select: none; }
+.source tr.covered td { background: #d9ead3; }
+.source tr.uncovered td { background: #f4cccc; }
+.source tr.partial td { background: #fff2cc; }
+.functions { margin: 1em 0; font-size: 0.9em; }
+.functions td { padding: 0.1em 0.8em; }
+.functions tr.uncovered td { background: #f4cccc; }
+";
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn page_names(files: &[FileCoverage], source_base: &str) -> HashMap<String, String> {
+    let mut used = HashSet::new();
+    let mut names = HashMap::new();
+    for file in files {
+        let stem: String = relative_to(&file.path, source_base)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect();
+        let mut name = format!("{}.html", stem);
+        let mut suffix = 1;
+        while !used.insert(name.clone()) {
+            suffix += 1;
+            name = format!("{}-{}.html", stem, suffix);
+        }
+        names.insert(file.path.clone(), name);
+    }
+    names
+}
+
+fn rate_class(rate: f64) -> &'static str {
+    if rate >= 0.8 {
+        "high"
+    } else if rate >= 0.5 {
+        "medium"
+    } else {
+        "low"
+    }
+}
+
+fn write_summary_cells<W: Write>(writer: &mut W, covered: usize, valid: usize) -> io::Result<()> {
+    let rate = line_rate(covered, valid);
+    write!(
+        writer,
+        "<td><span class=\"bar\"><span style=\"width: {:.0}%\"></span></span></td><td class=\"{}\">{:.2}%</td><td>{} / {}</td>",
+        rate * 100.0,
+        rate_class(rate),
+        rate * 100.0,
+        covered,
+        valid
+    )
+}
+
+fn write_header<W: Write>(writer: &mut W, title: &str) -> io::Result<()> {
+    write!(
+        writer,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n",
+        escape_html(title),
+        STYLE
+    )
+}
+
+fn write_footer<W: Write>(writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "</body>\n</html>")
+}
+
+impl ManyCoverage {
+    pub fn write_html(&self, output_dir: &Path, source_base: &str) -> io::Result<()> {
+        let files_dir = output_dir.join("files");
+        fs::create_dir_all(&files_dir)?;
+
+        let page_names = page_names(&self.files, source_base);
+
+        let mut index = BufWriter::new(File::create(output_dir.join("index.html"))?);
+        write_header(&mut index, "Coverage report")?;
+        writeln!(index, "<h1>Coverage report</h1>")?;
+        writeln!(index, "<table class=\"summary\">")?;
+        writeln!(index, "<tr><th>File</th><th colspan=\"3\">Lines</th><th colspan=\"3\">Functions</th></tr>")?;
+
+        let (total_covered, total_valid) = count_lines(&self.files);
+        let (functions_covered, functions_valid) = count_functions(&self.files);
+        write!(index, "<tr class=\"directory\"><td>All files</td>")?;
+        write_summary_cells(&mut index, total_covered, total_valid)?;
+        write_summary_cells(&mut index, functions_covered, functions_valid)?;
+        writeln!(index, "</tr>")?;
+
+        for (directory, files) in self.files_by_directory() {
+            let (covered, valid) = count_lines(files.iter().cloned());
+            let (functions_covered, functions_valid) = count_functions(files.iter().cloned());
+            let directory = relative_to(&directory, source_base);
+            write!(
+                index,
+                "<tr class=\"directory\"><td>{}</td>",
+                escape_html(if directory.is_empty() { "." } else { directory })
+            )?;
+            write_summary_cells(&mut index, covered, valid)?;
+            write_summary_cells(&mut index, functions_covered, functions_valid)?;
+            writeln!(index, "</tr>")?;
+
+            for file in files {
+                let file_name = Path::new(&file.path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| file.path.clone());
+                write!(
+                    index,
+                    "<tr><td class=\"file\"><a href=\"files/{}\">{}</a></td>",
+                    escape_html(&page_names[&file.path]),
+                    escape_html(&file_name)
+                )?;
+                write_summary_cells(&mut index, file.lines_covered(), file.lines.len())?;
+                write_summary_cells(&mut index, file.functions_covered(), file.functions.len())?;
+                writeln!(index, "</tr>")?;
+            }
+        }
+
+        writeln!(index, "</table>")?;
+        write_footer(&mut index)?;
+        index.flush()?;
+
+        for file in &self.files {
+            let mut page = BufWriter::new(File::create(files_dir.join(&page_names[&file.path]))?);
+            write_file_page(&mut page, file, source_base)?;
+            page.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_file_page<W: Write>(writer: &mut W, file: &FileCoverage, source_base: &str) -> io::Result<()> {
+    let display_path = relative_to(&file.path, source_base);
+    write_header(writer, display_path)?;
+    writeln!(
+        writer,
+        "<h1><a href=\"../index.html\">All files</a> / {}</h1>",
+        escape_html(display_path)
+    )?;
+
+    writeln!(writer, "<table class=\"summary\"><tr><th colspan=\"3\">Lines</th><th colspan=\"3\">Functions</th></tr><tr>")?;
+    write_summary_cells(writer, file.lines_covered(), file.lines.len())?;
+    write_summary_cells(writer, file.functions_covered(), file.functions.len())?;
+    writeln!(writer, "</tr></table>")?;
+
+    if !file.functions.is_empty() {
+        writeln!(writer, "<table class=\"functions\"><tr><th>Function</th><th>Line</th><th>Calls</th></tr>")?;
+        for function in &file.functions {
+            writeln!(
+                writer,
+                "<tr{}><td>{}</td><td><a href=\"#L{}\">{}</a></td><td>{}</td></tr>",
+                if function.calls == 0 { " class=\"uncovered\"" } else { "" },
+                escape_html(&function.name),
+                function.line_number,
+                function.line_number,
+                function.calls
+            )?;
+        }
+        writeln!(writer, "</table>")?;
+    }
+
+    let executable: HashMap<_, _> = file.lines.iter().map(|line| (line.line_number, line)).collect();
+
+    match file.read_source() {
+        Some(source) => {
+            writeln!(writer, "<table class=\"source\">")?;
+            for (idx, text) in split_lines(&source).into_iter().enumerate() {
+                let line_number = idx + 1;
+                let (class, hits) = match executable.get(&line_number).map(|line| (line.state(), line.hits)) {
+                    Some((LineState::Covered, hits)) => (" class=\"covered\"", format!("{}x", hits)),
+                    Some((LineState::Partial, hits)) => (" class=\"partial\"", format!("{}x", hits)),
+                    Some((LineState::Uncovered, _)) => (" class=\"uncovered\"", "0x".to_owned()),
+                    None => ("", String::new()),
+                };
+                writeln!(
+                    writer,
+                    "<tr id=\"L{}\"{}><td class=\"line-number\">{}</td><td class=\"hits\">{}</td><td>{}</td></tr>",
+                    line_number,
+                    class,
+                    line_number,
+                    hits,
+                    escape_html(text)
+                )?;
+            }
+            writeln!(writer, "</table>")?;
+        }
+        None => {
+            writeln!(writer, "<p>The source for this file is not available.</p>")?;
+        }
+    }
+
+    write_footer(writer)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::html::{escape_html, page_names, write_file_page};
+    use crate::lines::{FileCoverage, FunctionCoverage, LineCoverage, ManyCoverage};
+    use crate::test_util;
+    use std::fs;
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(escape_html("if (a < b && c > \"d\") {}"), "if (a &lt; b &amp;&amp; c &gt; &quot;d&quot;) {}");
+    }
+
+    #[test]
+    fn test_page_names_are_unique() {
+        let files = vec![
+            FileCoverage { path: "/base/src/a_b.js".to_string(), lines: vec![], functions: vec![], branches: vec![], source: None },
+            FileCoverage { path: "/base/src/a/b.js".to_string(), lines: vec![], functions: vec![], branches: vec![], source: None },
+        ];
+        let names = page_names(&files, "/base/");
+
+        assert_eq!(names["/base/src/a_b.js"], "src_a_b.js.html");
+        assert_eq!(names["/base/src/a/b.js"], "src_a_b.js-2.html");
+    }
+
+    #[test]
+    fn test_file_page_lists_functions() {
+        let file = FileCoverage {
+            path: "/base/src/a.js".to_string(),
+            lines: vec![],
+            functions: vec![
+                FunctionCoverage { name: "used".to_string(), line_number: 1, calls: 3 },
+                FunctionCoverage { name: "unused".to_string(), line_number: 2, calls: 0 },
+            ],
+            branches: vec![],
+            source: Some("function used() {}\nfunction unused() {}\n".to_string()),
+        };
+
+        let mut output = vec![];
+        write_file_page(&mut output, &file, "/base/").unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains(r##"<tr><td>used</td><td><a href="#L1">1</a></td><td>3</td></tr>"##));
+        assert!(output.contains(r##"<tr class="uncovered"><td>unused</td><td><a href="#L2">2</a></td><td>0</td></tr>"##));
+        assert!(output.contains(r#"<tr id="L2"><td class="line-number">2</td>"#));
+    }
+
+    #[test]
+    fn test_write_html() {
+        let line = |line_number, hits, partial| LineCoverage { line_number, hits, partial };
+        let coverage = ManyCoverage {
+            files: vec![
+                FileCoverage {
+                    path: "/base/src/a.js".to_string(),
+                    lines: vec![line(1, 2, false), line(2, 1, true), line(3, 0, false)],
+                    functions: vec![],
+                    branches: vec![],
+                    source: Some("a();\nif (x) b();\nc();\n".to_string()),
+                },
+                FileCoverage {
+                    path: "/base/src/lib/b.js".to_string(),
+                    lines: vec![line(1, 1, false)],
+                    functions: vec![],
+                    branches: vec![],
+                    source: Some("d();\n".to_string()),
+                },
+            ],
+        };
+
+        let output_dir = test_util::temp_dir("html");
+        coverage.write_html(&output_dir, "/base/").unwrap();
+        let index = fs::read_to_string(output_dir.join("index.html")).unwrap();
+        let mut pages = fs::read_dir(output_dir.join("files"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        pages.sort();
+        let page = fs::read_to_string(output_dir.join("files/src_a.js.html")).unwrap();
+        fs::remove_dir_all(&output_dir).unwrap();
+
+        // All files, then src/a.js and src/lib/b.js
+        assert!(index.contains(r#"<td class="medium">75.00%</td><td>3 / 4</td>"#));
+        assert!(index.contains(r#"<td class="medium">66.67%</td><td>2 / 3</td>"#));
+        assert!(index.contains(r#"<td class="high">100.00%</td><td>1 / 1</td>"#));
+        assert!(index.contains(r#"<a href="files/src_a.js.html">a.js</a>"#));
+        assert_eq!(pages, vec!["src_a.js.html", "src_lib_b.js.html"]);
+
+        assert!(page.contains(r#"<tr id="L1" class="covered"><td class="line-number">1</td><td class="hits">2x</td>"#));
+        assert!(page.contains(r#"<tr id="L2" class="partial"><td class="line-number">2</td><td class="hits">1x</td>"#));
+        assert!(page.contains(r#"<tr id="L3" class="uncovered"><td class="line-number">3</td><td class="hits">0x</td>"#));
+    }
+}
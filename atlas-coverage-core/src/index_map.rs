@@ -0,0 +1,205 @@
+use crate::model::{IndexSourceMap, SourceMap, SourceMapSection};
+use crate::util;
+use crate::vlq_decode::{MappingData, MappingEntry, SourceEntry};
+use serde_json::Value;
+use std::error::Error;
+use std::iter;
+use std::path::Path;
+
+/// Reads a source map, flattening an index map (one made of `sections`) into a regular one.
+/// Section urls are resolved against `base_dir`, the directory of the map itself.
+pub fn parse_source_map(value: Value, base_dir: &Path) -> Result<SourceMap, Box<dyn Error>> {
+    let mut source_map = if value.get("sections").is_none() {
+        ::serde_json::from_value(value)?
+    } else {
+        flatten(::serde_json::from_value(value)?, base_dir)?
+    };
+    source_map.dir = base_dir.to_path_buf();
+    Ok(source_map)
+}
+
+pub fn is_relative(url: &str) -> bool {
+    !url.contains("://") && !url.starts_with('/')
+}
+
+fn load_section(section: SourceMapSection, base_dir: &Path) -> Result<SourceMap, Box<dyn Error>> {
+    let url = match (section.map, section.url) {
+        (Some(map), _) => return parse_source_map(map, base_dir),
+        (None, Some(url)) => url,
+        (None, None) => return Err("A source map section needs either a map or a url".into()),
+    };
+
+    if let Some(decoded) = util::decode_data_url(&url) {
+        return parse_source_map(::serde_json::from_str(&decoded?)?, base_dir);
+    }
+
+    let path = match util::file_url_to_path(&url) {
+        Some(path) => Path::new(&path).to_path_buf(),
+        None if is_relative(&url) => base_dir.join(&url),
+        None => return Err(format!("Can't load the source map section {}", url).into()),
+    };
+    let section_dir = path.parent().unwrap_or(base_dir);
+    let mut source_map = parse_source_map(util::deserialize_object(&path)?, section_dir)?;
+
+    // Sources are relative to the map they're listed in, which isn't where the index map is
+    let url_dir = match util::file_url_to_path(&url) {
+        Some(_) => section_dir.to_path_buf(),
+        None => Path::new(&url).parent().unwrap_or_else(|| Path::new("")).to_path_buf(),
+    };
+    let mut sources = source_map.source_urls();
+    for source in sources.iter_mut() {
+        if is_relative(source) {
+            *source = url_dir.join(&source).to_string_lossy().to_string();
+        }
+    }
+    source_map.sources = sources;
+    source_map.sourceRoot = None;
+    Ok(source_map)
+}
+
+fn flatten(index_map: IndexSourceMap, base_dir: &Path) -> Result<SourceMap, Box<dyn Error>> {
+    let mut sources = vec![];
+    let mut sources_content = vec![];
+    let mut has_content = false;
+    let mut names = vec![];
+    let mut entries = vec![];
+
+    for section in index_map.sections {
+        let offset = (section.offset.line, section.offset.column);
+        let source_map = load_section(section, base_dir)?;
+        let (source_offset, name_offset) = (sources.len() as i64, names.len() as i64);
+
+        for entry in MappingData::new(source_map.mappings.as_bytes()) {
+            entries.push(MappingEntry {
+                gen_line: entry.gen_line + offset.0,
+                // Only the first line of a section is shifted over
+                gen_column: if entry.gen_line == 0 { entry.gen_column + offset.1 } else { entry.gen_column },
+                source: entry.source.map(|source| SourceEntry {
+                    source_idx: source.source_idx + source_offset,
+                    name_idx: source.name_idx.map(|name_idx| name_idx + name_offset),
+                    ..source
+                }),
+            });
+        }
+
+        let source_count = source_map.sources.len();
+        sources.extend(source_map.source_urls());
+        has_content |= source_map.sourcesContent.is_some();
+        sources_content.extend(
+            source_map.sourcesContent.unwrap_or_default().into_iter().chain(iter::repeat(None)).take(source_count),
+        );
+        names.extend(source_map.names);
+    }
+
+    Ok(SourceMap {
+        version: 3,
+        file: index_map.file,
+        sourceRoot: None,
+        sources,
+        sourcesContent: if has_content { Some(sources_content) } else { None },
+        names,
+        mappings: encode_mappings(entries),
+        dir: base_dir.to_path_buf(),
+    })
+}
+
+pub fn encode_mappings(mut entries: Vec<MappingEntry>) -> String {
+    entries.sort_by_key(|entry| (entry.gen_line, entry.gen_column));
+
+    fn encode(value: i64, mappings: &mut Vec<u8>) {
+        ::vlq::encode(value, mappings).expect("Could not encode source map mappings")
+    }
+
+    let mut mappings = vec![];
+    let (mut line, mut column, mut first_on_line) = (0, 0, true);
+    let (mut source_idx, mut source_line, mut source_col, mut name_idx) = (0, 0, 0, 0);
+
+    for entry in entries {
+        while line < entry.gen_line {
+            mappings.push(b';');
+            line += 1;
+            column = 0;
+            first_on_line = true;
+        }
+        if !first_on_line {
+            mappings.push(b',');
+        }
+        first_on_line = false;
+
+        encode(entry.gen_column - column, &mut mappings);
+        column = entry.gen_column;
+
+        if let Some(source) = entry.source {
+            encode(source.source_idx - source_idx, &mut mappings);
+            encode(source.source_line - source_line, &mut mappings);
+            encode(source.source_col - source_col, &mut mappings);
+            source_idx = source.source_idx;
+            source_line = source.source_line;
+            source_col = source.source_col;
+
+            if let Some(name) = source.name_idx {
+                encode(name - name_idx, &mut mappings);
+                name_idx = name;
+            }
+        }
+    }
+
+    String::from_utf8(mappings).expect("Source map mappings are ascii")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index_map::parse_source_map;
+    use crate::vlq_decode::MappingData;
+    use std::path::Path;
+
+    #[test]
+    fn test_flatten_sections() {
+        let index_map = ::serde_json::json!({
+            "version": 3,
+            "file": "bundle.js",
+            "sections": [
+                {
+                    "offset": { "line": 0, "column": 0 },
+                    "map": {
+                        "version": 3, "file": "a.js", "sourceRoot": "", "sources": ["a.js"],
+                        "sourcesContent": ["a();"], "names": ["a"], "mappings": "AAAAA;AACA"
+                    }
+                },
+                {
+                    "offset": { "line": 1, "column": 10 },
+                    "map": {
+                        "version": 3, "sourceRoot": "lib", "sources": ["b.js"],
+                        "names": [], "mappings": "AAAA,EAAC;AACA"
+                    }
+                }
+            ]
+        });
+
+        let source_map = parse_source_map(index_map, Path::new("/dist")).unwrap();
+        assert_eq!(source_map.sources, vec!["a.js", "lib/b.js"]);
+        assert_eq!(source_map.sourcesContent, Some(vec![Some("a();".to_owned()), None]));
+        assert_eq!(source_map.names, vec!["a"]);
+
+        let mappings = MappingData::new(source_map.mappings.as_bytes())
+            .map(|v| {
+                let source = v.source.unwrap();
+                (v.gen_line, v.gen_column, source.source_idx, source.source_line, source.source_col)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            mappings,
+            vec![(0, 0, 0, 0, 0), (1, 0, 0, 1, 0), (1, 10, 1, 0, 0), (1, 12, 1, 0, 1), (2, 0, 1, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_section_without_map() {
+        let index_map = ::serde_json::json!({
+            "version": 3,
+            "sections": [{ "offset": { "line": 0, "column": 0 } }]
+        });
+
+        assert!(parse_source_map(index_map, Path::new("/dist")).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use crate::branches::BranchKind;
+use crate::lines::{FileCoverage, ManyCoverage};
+use crate::offsets::split_lines;
+use crate::range::Count;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+#[derive(Debug, Serialize)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Location {
+    start: Position,
+    end: Position,
+}
+
+#[derive(Debug, Serialize)]
+struct FunctionLocation<'a> {
+    name: &'a str,
+    decl: Location,
+    loc: Location,
+    line: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct BranchMapping {
+    line: usize,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    loc: Location,
+    locations: Vec<Location>,
+}
+
+#[derive(Debug, Serialize)]
+#[allow(non_snake_case)]
+struct IstanbulFileCoverage<'a> {
+    path: &'a str,
+    statementMap: BTreeMap<usize, Location>,
+    s: BTreeMap<usize, Count>,
+    fnMap: BTreeMap<usize, FunctionLocation<'a>>,
+    f: BTreeMap<usize, Count>,
+    branchMap: BTreeMap<usize, BranchMapping>,
+    b: BTreeMap<usize, Vec<Count>>,
+}
+
+/// The name istanbul gives to a kind of branch
+fn branch_type(kind: BranchKind) -> &'static str {
+    match kind {
+        BranchKind::If => "if",
+        BranchKind::Ternary => "cond-expr",
+        BranchKind::Logical => "binary-expr",
+        BranchKind::Switch => "switch",
+        BranchKind::DefaultParameter => "default-arg",
+    }
+}
+
+/// The whole of a line, since only lines are known rather than exact locations
+fn line_location(line_number: usize, line_lengths: &[usize]) -> Location {
+    let line_length = line_lengths.get(line_number - 1).cloned().unwrap_or(0);
+    Location {
+        start: Position { line: line_number, column: 0 },
+        end: Position { line: line_number, column: line_length },
+    }
+}
+
+fn line_lengths(file: &FileCoverage) -> Vec<usize> {
+    file.read_source()
+        .map(|content| split_lines(&content).iter().map(|line| line.encode_utf16().count()).collect())
+        .unwrap_or_default()
+}
+
+impl<'a> IstanbulFileCoverage<'a> {
+    fn new(file: &'a FileCoverage, line_lengths: &[usize]) -> IstanbulFileCoverage<'a> {
+        let mut statement_map = BTreeMap::new();
+        let mut statement_hits = BTreeMap::new();
+
+        for (statement_id, line) in file.lines.iter().enumerate() {
+            statement_map.insert(statement_id, line_location(line.line_number, line_lengths));
+            statement_hits.insert(statement_id, line.hits);
+        }
+
+        let mut function_map = BTreeMap::new();
+        let mut function_hits = BTreeMap::new();
+        for (function_id, function) in file.functions.iter().enumerate() {
+            function_map.insert(
+                function_id,
+                FunctionLocation {
+                    name: &function.name,
+                    decl: line_location(function.line_number, line_lengths),
+                    loc: line_location(function.line_number, line_lengths),
+                    line: function.line_number,
+                },
+            );
+            function_hits.insert(function_id, function.calls);
+        }
+
+        let mut branch_map = BTreeMap::new();
+        let mut branch_hits = BTreeMap::new();
+        for (branch_id, branch) in file.branches.iter().enumerate() {
+            branch_map.insert(
+                branch_id,
+                BranchMapping {
+                    line: branch.line_number,
+                    kind: branch_type(branch.kind),
+                    loc: line_location(branch.line_number, line_lengths),
+                    locations: branch.hits.iter().map(|_| line_location(branch.line_number, line_lengths)).collect(),
+                },
+            );
+            branch_hits.insert(branch_id, branch.hits.clone());
+        }
+
+        IstanbulFileCoverage {
+            path: &file.path,
+            statementMap: statement_map,
+            s: statement_hits,
+            fnMap: function_map,
+            f: function_hits,
+            branchMap: branch_map,
+            b: branch_hits,
+        }
+    }
+}
+
+impl ManyCoverage {
+    pub fn write_istanbul_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let lengths = self.files.iter().map(line_lengths).collect::<Vec<_>>();
+        let coverage: BTreeMap<&str, IstanbulFileCoverage<'_>> = self
+            .files
+            .iter()
+            .zip(lengths.iter())
+            .map(|(file, lengths)| (file.path.as_str(), IstanbulFileCoverage::new(file, lengths)))
+            .collect();
+
+        ::serde_json::to_writer(&mut writer, &coverage)?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::branches::BranchKind;
+    use crate::lines::{BranchCoverage, FileCoverage, FunctionCoverage, LineCoverage, ManyCoverage};
+    use serde_json::Value;
+
+    #[test]
+    fn test_write_istanbul_json() {
+        let many_coverage = ManyCoverage {
+            files: vec![FileCoverage {
+                path: "/does/not/exist/a.js".to_string(),
+                lines: vec![
+                    LineCoverage { line_number: 2, hits: 4, partial: false },
+                    LineCoverage { line_number: 5, hits: 0, partial: false },
+                ],
+                functions: vec![FunctionCoverage { name: "render".to_string(), line_number: 2, calls: 4 }],
+                branches: vec![
+                    BranchCoverage { kind: BranchKind::If, line_number: 2, hits: vec![3, 1] },
+                    BranchCoverage { kind: BranchKind::Logical, line_number: 5, hits: vec![0, 0, 0] },
+                ],
+                source: None,
+            }],
+        };
+
+        let mut output = vec![];
+        many_coverage.write_istanbul_json(&mut output).unwrap();
+        let output: Value = ::serde_json::from_slice(&output).unwrap();
+
+        let file = &output["/does/not/exist/a.js"];
+        assert_eq!(file["path"], "/does/not/exist/a.js");
+        assert_eq!(file["statementMap"]["0"]["start"]["line"], 2);
+        assert_eq!(file["statementMap"]["1"]["start"]["line"], 5);
+        assert_eq!(file["s"]["0"], 4);
+        assert_eq!(file["s"]["1"], 0);
+        assert_eq!(file["fnMap"]["0"]["name"], "render");
+        assert_eq!(file["fnMap"]["0"]["decl"]["start"]["line"], 2);
+        assert_eq!(file["f"]["0"], 4);
+        assert_eq!(file["branchMap"]["0"]["type"], "if");
+        assert_eq!(file["branchMap"]["0"]["line"], 2);
+        assert_eq!(file["branchMap"]["0"]["loc"]["start"]["line"], 2);
+        assert_eq!(file["branchMap"]["0"]["locations"].as_array().unwrap().len(), 2);
+        assert_eq!(file["branchMap"]["1"]["type"], "binary-expr");
+        assert_eq!(file["branchMap"]["1"]["locations"][2]["start"]["line"], 5);
+        assert_eq!(file["b"]["0"], ::serde_json::json!([3, 1]));
+        assert_eq!(file["b"]["1"], ::serde_json::json!([0, 0, 0]));
+    }
+}
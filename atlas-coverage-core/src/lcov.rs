@@ -1,4 +1,7 @@
 use lcov_parser:: { LCOVRecord };
+use crate::lines::{FunctionCoverage, ManyCoverage};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
 
 /// Iterator yielding every line in a string. The line includes newline character(s).
 pub struct LinesWithEndings<'a> {
@@ -111,4 +114,142 @@ impl <'a> Iterator for LcovFilesLines<'a> {
             }
         })
     }
-}
\ No newline at end of file
+}
+
+/// The names of the functions of a file, which lcov uses to match `FN` and `FNDA` records. Names
+/// that are used more than once get the function's line, like `render@12`.
+fn function_names(functions: &[FunctionCoverage]) -> Vec<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for function in functions {
+        *counts.entry(&function.name).or_insert(0) += 1;
+    }
+
+    let mut used = HashSet::new();
+    functions
+        .iter()
+        .map(|function| {
+            let mut name = if counts[function.name.as_str()] > 1 {
+                format!("{}@{}", function.name, function.line_number)
+            } else {
+                function.name.clone()
+            };
+            // Functions with the same name on the same line
+            let base = name.clone();
+            let mut nth = 1;
+            while !used.insert(name.clone()) {
+                nth += 1;
+                name = format!("{}#{}", base, nth);
+            }
+            name
+        })
+        .collect()
+}
+
+impl ManyCoverage {
+    pub fn write_lcov<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for file in &self.files {
+            writeln!(writer, "TN:")?;
+            writeln!(writer, "SF:{}", file.path)?;
+            let names = function_names(&file.functions);
+            for (function, name) in file.functions.iter().zip(&names) {
+                writeln!(writer, "FN:{},{}", function.line_number, name)?;
+            }
+            for (function, name) in file.functions.iter().zip(&names) {
+                writeln!(writer, "FNDA:{},{}", function.calls, name)?;
+            }
+            if !file.functions.is_empty() {
+                writeln!(writer, "FNF:{}", file.functions.len())?;
+                writeln!(writer, "FNH:{}", file.functions_covered())?;
+            }
+            let line_hits: HashMap<_, _> = file.lines.iter().map(|line| (line.line_number, line.hits)).collect();
+            for (block, branch) in file.branches.iter().enumerate() {
+                // `-` when the code around the branch point never ran, rather than it not being taken
+                let executed = line_hits.get(&branch.line_number).is_none_or(|hits| *hits > 0);
+                for (idx, hits) in branch.hits.iter().enumerate() {
+                    let taken = if executed { hits.to_string() } else { "-".to_owned() };
+                    writeln!(writer, "BRDA:{},{},{},{}", branch.line_number, block, idx, taken)?;
+                }
+            }
+            if !file.branches.is_empty() {
+                writeln!(writer, "BRF:{}", file.branches_valid())?;
+                writeln!(writer, "BRH:{}", file.branches_covered())?;
+            }
+            for line in &file.lines {
+                writeln!(writer, "DA:{},{}", line.line_number, line.hits)?;
+            }
+            writeln!(writer, "LF:{}", file.lines.len())?;
+            writeln!(writer, "LH:{}", file.lines_covered())?;
+            writeln!(writer, "end_of_record")?;
+        }
+
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::branches::BranchKind;
+    use crate::lcov::{function_names, LcovFilesLines};
+    use crate::lines::{BranchCoverage, FileCoverage, FunctionCoverage, LineCoverage, ManyCoverage};
+
+    #[test]
+    fn test_write_lcov() {
+        let many_coverage = ManyCoverage {
+            files: vec![
+                FileCoverage {
+                    path: "/src/a.js".to_string(),
+                    lines: vec![
+                        LineCoverage { line_number: 1, hits: 2, partial: false },
+                        LineCoverage { line_number: 3, hits: 0, partial: false },
+                    ],
+                    functions: vec![],
+                    branches: vec![
+                        BranchCoverage { kind: BranchKind::If, line_number: 1, hits: vec![2, 0] },
+                        BranchCoverage { kind: BranchKind::Ternary, line_number: 3, hits: vec![0, 0] },
+                    ],
+                    source: None,
+                },
+                FileCoverage {
+                    path: "/src/b.js".to_string(),
+                    lines: vec![LineCoverage { line_number: 2, hits: 1, partial: false }],
+                    functions: vec![
+                        FunctionCoverage { name: "run".to_string(), line_number: 1, calls: 1 },
+                        FunctionCoverage { name: "(anonymous_1)".to_string(), line_number: 4, calls: 0 },
+                    ],
+                    branches: vec![],
+                    source: None,
+                },
+            ],
+        };
+
+        let mut output = vec![];
+        many_coverage.write_lcov(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(
+            output,
+            "TN:\nSF:/src/a.js\nBRDA:1,0,0,2\nBRDA:1,0,1,0\nBRDA:3,1,0,-\nBRDA:3,1,1,-\nBRF:4\nBRH:1\nDA:1,2\nDA:3,0\nLF:2\nLH:1\nend_of_record\n\
+             TN:\nSF:/src/b.js\nFN:1,run\nFN:4,(anonymous_1)\nFNDA:1,run\nFNDA:0,(anonymous_1)\nFNF:2\nFNH:1\nDA:2,1\nLF:1\nLH:1\nend_of_record\n"
+        );
+
+        let parsed = LcovFilesLines::new(&output).collect::<Vec<_>>();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].file_path, "/src/a.js");
+        assert_eq!(parsed[0].lines, vec![1, 3]);
+        assert_eq!(parsed[1].lines, vec![2]);
+    }
+
+    #[test]
+    fn test_function_names_are_unique() {
+        let function = |name: &str, line_number| FunctionCoverage { name: name.to_string(), line_number, calls: 0 };
+        let functions = vec![
+            function("render", 2),
+            function("update", 5),
+            function("render", 9),
+            function("(anonymous_3)", 12),
+            function("(anonymous_3)", 12),
+        ];
+
+        assert_eq!(function_names(&functions), vec!["render@2", "update", "render@9", "(anonymous_3)@12", "(anonymous_3)@12#2"]);
+    }
+}
@@ -11,105 +11,339 @@ extern crate memmap;
 pub mod debug;
 pub mod settings;
 
+mod branches;
+mod chain;
+mod cobertura;
+mod conflicts;
+mod functions;
+mod html;
+mod index_map;
+mod istanbul;
 mod lcov;
 mod lines;
 mod load;
 mod model;
+mod offsets;
 mod range;
 mod source_map;
+mod statements;
+mod unloaded;
 mod vlq_decode;
 mod util;
 
-use std::path::Path;
+#[cfg(test)]
+mod test_util;
+
+use std::path::{Path, PathBuf};
+
+pub use crate::conflicts::Conflict;
 
 use crate::lines::calculate_executable_line_mappings;
 use crate::lines::calculate_line_coverage;
 use crate::lines::FileCoverage;
 use crate::lines::ManyCoverage;
 use crate::model::{PuppeteerData, SourceMap};
-use crate::settings::Settings;
+use crate::settings::{ConflictResolution, Settings};
 use crate::source_map::*;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::io::Write;
 use std::io;
+use std::fmt;
+use std::str::FromStr;
 
-pub fn process_source_map(settings: &Settings, data: PuppeteerData) -> Option<Vec<FileCoverage>> {
-    if let Some(source_mapping_url) = data.get_source_mapping_url() {
-        let source_path = data.url.replace(&settings.public_url_base, &settings.dist_path);
-
-        let source_mapping_url = source_mapping_url.replace("//# sourceMappingURL=", "");
-        let source_mapping_path = Path::new(&source_path)
-            .parent()
-            .unwrap()
-            .join(source_mapping_url);
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Sonar,
+    Lcov,
+    Cobertura,
+    Istanbul,
+    Html,
+}
 
-        println!("Processing source map {}", source_mapping_path.to_string_lossy());
+impl FromStr for OutputFormat {
+    type Err = String;
 
-        let source_mapping_path = Path::new(&source_mapping_path);
-        if source_mapping_path.exists() {
-            let source_map: SourceMap = match util::deserialize_object(source_mapping_path) {
-                Ok(source_map) => source_map,
-                Err(err) => {
-                    eprintln!("Couldn't deserialize source map for {}", source_mapping_path.to_string_lossy());
-                    eprintln!("{}", err);
-                    return None
-                },
-            };
+    fn from_str(value: &str) -> Result<OutputFormat, String> {
+        match value {
+            "sonar" => Ok(OutputFormat::Sonar),
+            "lcov" => Ok(OutputFormat::Lcov),
+            "cobertura" => Ok(OutputFormat::Cobertura),
+            "istanbul" => Ok(OutputFormat::Istanbul),
+            "html" => Ok(OutputFormat::Html),
+            _ => Err(format!("Unknown output format '{}', expected one of: sonar, lcov, cobertura, istanbul, html", value)),
+        }
+    }
+}
 
-            let references = process_references(&settings, &source_map);
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputFormat::Sonar => write!(f, "sonar"),
+            OutputFormat::Lcov => write!(f, "lcov"),
+            OutputFormat::Cobertura => write!(f, "cobertura"),
+            OutputFormat::Istanbul => write!(f, "istanbul"),
+            OutputFormat::Html => write!(f, "html"),
+        }
+    }
+}
 
-            let file_refs = references.iter().map(|s| s.file_path.clone()).collect();
-            let line_refs = calculate_executable_line_mappings(&source_map, references);
-            let mut file_coverage =
-                calculate_line_coverage(data.ranges, line_refs, file_refs, data.text.as_str());
+/// Where the script at `url` lives on disk. `file://` urls (e.g. from node) point straight at
+/// the file, everything else is served from the `dist_path`.
+fn script_path(settings: &Settings, url: &str) -> String {
+    match util::file_url_to_path(url) {
+        Some(path) => path,
+        None => url.replace(&settings.public_url_base, &settings.dist_path),
+    }
+}
 
-            if let Some(ref reify_against_lcov) = settings.reify_against_lcov {
-                file_coverage = {
-                    eprintln!("Reifying against LCOV file");
+/// Where the source map at `source_mapping_url`, as referenced from the script at `source_path`,
+/// lives on disk
+fn source_map_path(settings: &Settings, source_path: &str, source_mapping_url: &str) -> PathBuf {
+    if source_mapping_url.contains("://") {
+        return PathBuf::from(script_path(settings, source_mapping_url));
+    }
+    let relative_url = source_mapping_url.split(['?', '#']).next().unwrap_or("");
+    Path::new(source_path)
+        .parent()
+        .unwrap()
+        .join(util::percent_decode(relative_url))
+}
 
-                    let mut file_hash_map: HashMap<_,_> = file_coverage.into_iter().map(|v| (v.path.clone(), v)).collect();
+fn load_source_map(settings: &Settings, data: &mut PuppeteerData, source_path: &str) -> Option<SourceMap> {
+    let source_dir = Path::new(source_path).parent().unwrap();
 
-                    for line_data in lcov::LcovFilesLines::new(&util::fast_read(&reify_against_lcov).unwrap()) {
+    if let Some(embedded_source_map) = data.source_map.take() {
+        eprintln!("Processing embedded source map for {}", data.url);
 
-                        let our_coverage = file_hash_map.get_mut(&line_data.file_path);
-                        our_coverage.map(|our_coverage| {
-                            let new_lines : HashSet<_> = line_data.lines.into_iter().collect();
+        // The map's sources are relative to where it was stored, when that's known
+        let map_path = data.source_map_url.as_deref().and_then(util::file_url_to_path).map(PathBuf::from);
+        let map_dir = map_path.as_deref().and_then(Path::parent).unwrap_or(source_dir);
+        return match index_map::parse_source_map(embedded_source_map, map_dir) {
+            Ok(source_map) => Some(source_map),
+            Err(err) => {
+                eprintln!("Couldn't deserialize the embedded source map for {}", data.url);
+                eprintln!("{}", err);
+                None
+            },
+        };
+    }
 
-                            our_coverage.lines.retain(|v| new_lines.contains(&v.line_number));
-                        });
-                    }
+    // A url recorded next to the coverage (e.g. from the `SourceMap` header) wins over the comment
+    let source_mapping_path = match data.source_map_url.clone().or_else(|| data.get_source_mapping_url()) {
+        Some(source_mapping_url) => {
+            if let Some(decoded) = util::decode_data_url(&source_mapping_url) {
+                eprintln!("Processing inline source map for {}", data.url);
 
-                    file_hash_map.into_iter().map(|(_k,v)| v).collect()
-                }
+                let parsed = decoded
+                    .and_then(|content| Ok(::serde_json::from_str(&content)?))
+                    .and_then(|value| index_map::parse_source_map(value, source_dir));
+                return match parsed {
+                    Ok(source_map) => Some(source_map),
+                    Err(err) => {
+                        eprintln!("Couldn't deserialize the inline source map for {}", data.url);
+                        eprintln!("{}", err);
+                        None
+                    },
+                };
+            }
+            source_map_path(settings, source_path, &source_mapping_url)
+        },
+        None => {
+            // Fall back to the `<bundle>.map` naming convention
+            let sibling_path = PathBuf::from(format!("{}.map", source_path));
+            if !sibling_path.exists() {
+                eprintln!("No source map found for {}", data.url);
+                return None;
             }
+            sibling_path
+        },
+    };
 
-            Some(file_coverage)
-        } else {
+    eprintln!("Processing source map {}", source_mapping_path.to_string_lossy());
+
+    if !source_mapping_path.exists() {
+        eprintln!("The source map {} for {} doesn't exist", source_mapping_path.to_string_lossy(), data.url);
+        return None;
+    }
+
+    let source_mapping_dir = source_mapping_path.parent().unwrap_or(source_dir);
+    let parsed = util::deserialize_object(&source_mapping_path)
+        .and_then(|value| index_map::parse_source_map(value, source_mapping_dir));
+    match parsed {
+        Ok(source_map) => Some(source_map),
+        Err(err) => {
+            eprintln!("Couldn't deserialize source map for {}", source_mapping_path.to_string_lossy());
+            eprintln!("{}", err);
             None
+        },
+    }
+}
+
+/// Maps the coverage of a script onto its sources. Sources that changed since the source map was
+/// built are handled as the `on_conflict` setting says, and added to `conflicts`.
+pub fn process_source_map(settings: &Settings, mut data: PuppeteerData, conflicts: &mut Vec<Conflict>) -> Option<Vec<FileCoverage>> {
+    let source_path = script_path(settings, &data.url);
+
+    if data.text.is_empty() {
+        // Raw devtools coverage doesn't carry the script source, so use the bundle on disk
+        match util::fast_read(&source_path) {
+            Ok(text) => data.text = text,
+            Err(err) => {
+                eprintln!("Cannot read the script source for {} from {} - {}", data.url, source_path, err);
+                return None
+            },
         }
-    } else {
-        None
     }
+
+    let source_map = load_source_map(settings, &mut data, &source_path)?;
+    let source_map = chain::compose_source_maps(settings, source_map, &source_path);
+
+    let references = process_references(&settings, &source_map);
+
+    let file_refs = references.iter().map(|s| s.file_path.clone()).collect();
+    let conflicting = references
+        .iter()
+        .filter_map(|s| match s.file_path {
+            FilePath::Conflict(ref path) => Some(path.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    let line_refs = calculate_executable_line_mappings(&source_map, references);
+    let functions = functions::map_functions(&data.functions, &source_map, &data.text);
+    let file_coverage =
+        calculate_line_coverage(data.ranges, line_refs, functions, file_refs, data.text.as_str());
+    let mut file_coverage = conflicts::resolve_conflicts(settings, &data.url, &conflicting, file_coverage, conflicts);
+
+    if let Some(ref reify_against_lcov) = settings.reify_against_lcov {
+        file_coverage = {
+            eprintln!("Reifying against LCOV file");
+
+            let mut file_hash_map: HashMap<_,_> = file_coverage.into_iter().map(|v| (v.path.clone(), v)).collect();
+
+            for line_data in lcov::LcovFilesLines::new(&util::fast_read(&reify_against_lcov).unwrap()) {
+
+                let our_coverage = file_hash_map.get_mut(&line_data.file_path);
+                our_coverage.map(|our_coverage| {
+                    let new_lines : HashSet<_> = line_data.lines.into_iter().collect();
+
+                    our_coverage.lines.retain(|v| new_lines.contains(&v.line_number));
+                });
+            }
+
+            file_hash_map.into_iter().map(|(_k,v)| v).collect()
+        }
+    }
+
+    Some(file_coverage)
 }
 
-pub fn run<P: AsRef<Path>, W: Write>(settings: Settings, json_path: Vec<P>, writer: Option<W>) {
-    let values = load::load_items(json_path);
-    let processed: Vec<_> = values
+fn collect_coverage<P: AsRef<Path>>(settings: &Settings, json_path: Vec<P>) -> ManyCoverage {
+    let values: Vec<_> = load::load_items(json_path).into_iter().collect();
+    let loaded: HashSet<_> = values.iter().map(|value| unloaded::canonical(&script_path(settings, &value.url))).collect();
+    let mut conflicts = vec![];
+    let mut processed: Vec<_> = values
         .into_iter()
-        .map(|value| process_source_map(&settings, value))
+        .map(|value| process_source_map(settings, value, &mut conflicts))
         .flat_map(|value| value.into_iter())
         .flat_map(|value| value.into_iter())
         .collect();
 
-    let many_coverage = ManyCoverage { files: processed };
+    if settings.scan_dist {
+        let unloaded = unloaded::unloaded_bundles(settings, &loaded)
+            .into_iter()
+            .filter_map(|value| process_source_map(settings, value, &mut conflicts))
+            .flatten();
+        processed.extend(unloaded);
+    }
+    // A source shared by several bundles conflicts in each of them
+    let mut reported = HashSet::new();
+    conflicts.retain(|conflict| reported.insert(conflict.path.clone()));
+    conflicts::write_report(settings, &conflicts);
+
+    // Sources shared by several bundles are reported once
+    let mut processed = lines::merge_files(processed);
+
+    if settings.all_files {
+        // Skipped sources were loaded, they just can't be reported
+        let skipped: Vec<_> = conflicts
+            .iter()
+            .filter(|conflict| conflict.resolution == ConflictResolution::Skip)
+            .map(|conflict| conflict.path.clone())
+            .collect();
+        let unloaded = unloaded::unloaded_files(&settings.sources, &processed, &skipped);
+        processed.extend(unloaded);
+    }
+
+    ManyCoverage { files: processed }
+}
+
+/// Writes a coverage report to `writer`, or stdout when there isn't one. The html report is a
+/// directory of pages, so it's written by `run_html` instead.
+pub fn run<P: AsRef<Path>, W: Write>(settings: Settings, json_path: Vec<P>, format: OutputFormat, writer: Option<W>) -> io::Result<()> {
+    if format == OutputFormat::Html {
+        return Err(html_needs_run_html());
+    }
+
+    let many_coverage = collect_coverage(&settings, json_path);
 
     if let Some(writer) = writer {
-        many_coverage.write_xml(writer);
+        write_coverage(&many_coverage, &settings, format, writer)
     } else {
         let stdout = io::stdout();
         let handle = stdout.lock();
 
-        many_coverage.write_xml(handle);
+        write_coverage(&many_coverage, &settings, format, handle)
+    }
+}
+
+fn write_coverage<W: Write>(many_coverage: &ManyCoverage, settings: &Settings, format: OutputFormat, writer: W) -> io::Result<()> {
+    match format {
+        OutputFormat::Sonar => many_coverage.write_xml(writer),
+        OutputFormat::Lcov => many_coverage.write_lcov(writer),
+        OutputFormat::Cobertura => many_coverage.write_cobertura(writer, &settings.sources.base),
+        OutputFormat::Istanbul => many_coverage.write_istanbul_json(writer),
+        OutputFormat::Html => Err(html_needs_run_html()),
     }
-}
\ No newline at end of file
+}
+
+fn html_needs_run_html() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "The html report is written to a directory, use run_html instead")
+}
+
+pub fn run_html<P: AsRef<Path>>(settings: Settings, json_path: Vec<P>, output_dir: &Path) -> io::Result<()> {
+    let many_coverage = collect_coverage(&settings, json_path);
+
+    many_coverage.write_html(output_dir, &settings.sources.base)
+}
+#[cfg(test)]
+mod test {
+    use crate::load_source_map;
+    use crate::model::PuppeteerData;
+    use crate::range::RangeStack;
+    use crate::test_util;
+    use std::path::Path;
+
+    #[test]
+    fn test_embedded_source_map_dir() {
+        let settings = test_util::settings("/srv/app/", "/srv/app/dist/");
+        let embedded = ::serde_json::json!({ "version": 3, "sources": ["../../src/a.ts"], "names": [], "mappings": "AAAA" });
+        let data = |source_map_url: Option<&str>| PuppeteerData {
+            url: "file:///srv/app/dist/app.js".to_owned(),
+            text: String::new(),
+            ranges: RangeStack { ranges: vec![] },
+            source_map_url: source_map_url.map(String::from),
+            source_map: Some(embedded.clone()),
+            functions: vec![],
+        };
+
+        // Node recorded that the map lives in dist/maps, so that's what its sources are relative to
+        let mut stored_elsewhere = data(Some("file:///srv/app/dist/maps/app.js.map"));
+        let source_map = load_source_map(&settings, &mut stored_elsewhere, "/srv/app/dist/app.js").unwrap();
+        assert_eq!(source_map.dir, Path::new("/srv/app/dist/maps"));
+
+        let mut unknown = data(None);
+        let source_map = load_source_map(&settings, &mut unknown, "/srv/app/dist/app.js").unwrap();
+        assert_eq!(source_map.dir, Path::new("/srv/app/dist"));
+    }
+}
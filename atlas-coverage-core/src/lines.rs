@@ -1,32 +1,105 @@
+use crate::branches::{self, BranchKind, Position};
 use crate::model::SourceMap;
-use crate::range::RangeStack;
+use crate::offsets::OffsetIndex;
+use crate::range::{Count, RangeStack};
 use crate::source_map::FilePath;
 use crate::source_map::SourceMapSource;
-use std::collections::BTreeMap;
+use crate::statements;
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 use std::io::Write;
+use std::path::Path;
+use crate::util;
 use crate::vlq_decode;
 use xml::writer::XmlEvent;
 use xml::EventWriter;
 
-type LineRefs = Vec<Option<BTreeMap<usize, Vec<Coord>>>>;
+type LineRefs = Vec<Option<SourceLines>>;
 
+#[derive(Debug)]
+pub struct SourceLines {
+    content: String,
+    lines: BTreeMap<usize, Vec<Coord>>,
+    branches: Vec<BranchLocation>,
+}
+
+/// A generated line and column
+type Generated = (usize, usize);
+
+/// A branch point in a source file, with where it and each of its branches are in the generated
+/// code. Branches whose code isn't mapped are never taken.
+#[derive(Debug)]
+pub struct BranchLocation {
+    kind: BranchKind,
+    line: usize,
+    point: Generated,
+    arms: Vec<Option<Generated>>,
+    implicit_else: bool,
+}
+
+/// Where a mapping segment is in the generated code. It runs up to the next segment on the line,
+/// or to the end of the line when `end_col` is `None`.
 #[derive(Debug)]
 pub struct Coord {
     line: usize,
     col: usize,
+    end_col: Option<usize>,
 }
 
 #[derive(Debug)]
 pub struct FileCoverage {
     pub path: String,
     pub lines: Vec<LineCoverage>,
+    pub functions: Vec<FunctionCoverage>,
+    pub branches: Vec<BranchCoverage>,
+    pub source: Option<String>,
+}
+
+/// A function in a source file and the number of times it was called
+#[derive(Debug, Clone)]
+pub struct FunctionCoverage {
+    pub name: String,
+    pub line_number: usize,
+    pub calls: Count,
+}
+
+/// The hit counts of each of the branches of a branch point
+#[derive(Debug)]
+pub struct BranchCoverage {
+    pub kind: BranchKind,
+    pub line_number: usize,
+    pub hits: Vec<Count>,
 }
 
 #[derive(Debug)]
 pub struct LineCoverage {
     pub line_number: usize,
-    pub covered: bool,
+    pub hits: Count,
+    /// Whether only some of the line's code ran
+    pub partial: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineState {
+    Covered,
+    Partial,
+    Uncovered,
+}
+
+impl LineCoverage {
+    pub fn covered(&self) -> bool {
+        self.hits > 0
+    }
+
+    pub fn state(&self) -> LineState {
+        if !self.covered() {
+            LineState::Uncovered
+        } else if self.partial {
+            LineState::Partial
+        } else {
+            LineState::Covered
+        }
+    }
 }
 
 pub fn calculate_executable_line_mappings(
@@ -44,104 +117,483 @@ pub fn calculate_executable_line_mappings(
     let mut sources: LineRefs = references
         .into_iter()
         .map(SourceMapSource::convert_to_user_source)
-        .map(|v| v.map(|_| BTreeMap::new()))
+        .map(|v| v.map(|content| SourceLines { content, lines: BTreeMap::new(), branches: vec![] }))
         .collect::<Vec<_>>();
+    // The generated position of every mapped position in each source
+    let mut mapped: Vec<Vec<(Position, Generated)>> = sources.iter().map(|_| vec![]).collect();
 
-    for v in vlq_decode::MappingData::new(source_map.mappings.as_bytes()) {
+    let segments = vlq_decode::MappingData::new(source_map.mappings.as_bytes()).collect::<Vec<_>>();
+    for (idx, v) in segments.iter().enumerate() {
         if let Some(ref source_ref) = v.source {
+            let end_col = segments
+                .get(idx + 1)
+                .filter(|next| next.gen_line == v.gen_line)
+                .map(|next| next.gen_column as usize);
             if let Some(ref mut source_map) =
                 sources.get_mut(source_ref.source_idx as usize).unwrap()
             {
                 let coords = source_map
+                    .lines
                     .entry(source_ref.source_line as usize)
                     .or_insert(vec![]);
                 (*coords).push(Coord {
                     col: v.gen_column as usize,
                     line: v.gen_line as usize,
+                    end_col,
                 });
+                mapped[source_ref.source_idx as usize].push((
+                    Position { line: source_ref.source_line as usize, column: source_ref.source_col as usize },
+                    (v.gen_line as usize, v.gen_column as usize),
+                ));
             }
         }
     }
 
+    for (source, mut mapped) in sources.iter_mut().zip(mapped) {
+        if let Some(source) = source {
+            mapped.sort();
+            source.branches = branch_locations(&source.content, &mapped);
+
+            // Only lines starting a statement are executable, not the continuations of one
+            let statement_lines = statements::statement_lines(&source.content);
+            source.lines.retain(|line, _| statement_lines.contains(line));
+        }
+    }
+
     sources
 }
 
+/// Finds where the first mapped code at or after `position` in the source was generated
+fn generated_position(mapped: &[(Position, Generated)], position: Position) -> Option<(Position, Generated)> {
+    let idx = mapped.partition_point(|(source_position, _)| *source_position < position);
+    mapped.get(idx).cloned()
+}
+
+fn branch_locations(content: &str, mapped: &[(Position, Generated)]) -> Vec<BranchLocation> {
+    branches::branch_points(content)
+        .into_iter()
+        .filter_map(|point| {
+            // Branch points that didn't make it into the bundle aren't reported
+            let (found, generated) = generated_position(mapped, point.position)?;
+            if found.line != point.position.line {
+                return None;
+            }
+            Some(BranchLocation {
+                kind: point.kind,
+                line: point.position.line,
+                point: generated,
+                arms: point.arms.iter().map(|arm| generated_position(mapped, *arm).map(|(_, generated)| generated)).collect(),
+                implicit_else: point.implicit_else,
+            })
+        }).collect()
+}
+
 pub fn calculate_line_coverage(
     ranges: RangeStack,
     line_refs: LineRefs,
+    mut functions: Vec<Vec<FunctionCoverage>>,
     sources: Vec<FilePath>,
     minified: &str,
 ) -> Vec<FileCoverage> {
-    let idxs = {
-        let mut sum = 0;
-        minified
-            .lines()
-            .into_iter()
-            .map(|line| {
-                let ret = sum;
-                sum += 1;
-                sum = sum + line.len();
-                ret
-            }).collect::<Vec<usize>>()
-    };
+    let offsets = OffsetIndex::new(minified);
 
     sources
         .into_iter()
         .zip(line_refs)
-        .flat_map(|(source, maybe_exec_lines)| {
-            maybe_exec_lines.map(|exec_lines| FileCoverage {
-                lines: exec_lines
+        .enumerate()
+        .flat_map(|(idx, (source, maybe_exec_lines))| {
+            let functions = functions.get_mut(idx).map(std::mem::take).unwrap_or_default();
+            maybe_exec_lines.map(|exec_lines| {
+                let (branches, point_hits): (Vec<_>, Vec<_>) =
+                    exec_lines.branches.iter().map(|branch| branch_coverage(branch, &ranges, &offsets)).unzip();
+                let mut lines = exec_lines
+                    .lines
                     .into_iter()
-                    .map(|(line_number, coords)| LineCoverage {
-                        line_number: line_number + 1,
-                        covered: coords
+                    .map(|(line_number, coords)| {
+                        let spans = coords
                             .iter()
-                            .map(|v| idxs[v.line] + v.col)
-                            .any(|byte_idx| ranges.contains_value(byte_idx)),
-                    }).collect(),
-                path: match source {
-                    FilePath::Resolved(path_buf) => path_buf.to_string_lossy().to_string(),
-                    FilePath::Unresolved(_) => panic!("The file does not exist!"),
-                },
+                            .filter_map(|v| {
+                                let start = offsets.offset(v.line, v.col)?;
+                                let end = match v.end_col {
+                                    Some(end_col) => offsets.offset(v.line, end_col)?,
+                                    None => offsets.line_end(v.line)?,
+                                };
+                                Some(ranges.count_range(start, end))
+                            }).collect::<Vec<_>>();
+                        let hits = spans.iter().map(|(_, highest)| *highest).max().unwrap_or(0);
+                        LineCoverage {
+                            line_number: line_number + 1,
+                            hits,
+                            partial: hits > 0 && spans.iter().any(|(lowest, _)| *lowest == 0),
+                        }
+                    }).collect::<Vec<_>>();
+                add_branch_lines(&mut lines, branches.iter().map(|branch: &BranchCoverage| branch.line_number).zip(point_hits));
+
+                FileCoverage {
+                    functions,
+                    branches,
+                    lines,
+                    source: Some(exec_lines.content),
+                    path: match source {
+                        FilePath::Resolved(path_buf) | FilePath::Conflict(path_buf) => path_buf.to_string_lossy().to_string(),
+                        FilePath::Unresolved(path) => path,
+                    },
+                }
             })
         }).collect::<Vec<_>>()
 }
 
+/// The hits of each arm of a branch point, and of the point itself
+fn branch_coverage(branch: &BranchLocation, ranges: &RangeStack, offsets: &OffsetIndex) -> (BranchCoverage, Count) {
+    let count_at = |(line, col): Generated| offsets.offset(line, col).map_or(0, |offset| ranges.count_at(offset));
+    let point_hits = count_at(branch.point);
+    let mut hits: Vec<Count> = branch.arms.iter().map(|arm| arm.map_or(0, count_at)).collect();
+    if branch.implicit_else {
+        let taken: Count = hits.iter().sum();
+        hits.push(point_hits.saturating_sub(taken));
+    }
+    (BranchCoverage { kind: branch.kind, line_number: branch.line + 1, hits }, point_hits)
+}
+
+/// Branch points on a line that doesn't start a statement, like a default parameter of a
+/// function declaration or a ternary on a continuation line, make their line executable. That
+/// way every format reports them on their own line. `branch_lines` holds the line of each point
+/// and how often it was reached.
+pub fn add_branch_lines(lines: &mut Vec<LineCoverage>, branch_lines: impl IntoIterator<Item = (usize, Count)>) {
+    for (line_number, hits) in branch_lines {
+        match lines.binary_search_by_key(&line_number, |line| line.line_number) {
+            Ok(_) => {}
+            Err(idx) => lines.insert(idx, LineCoverage { line_number, hits, partial: false }),
+        }
+    }
+}
+
+impl FileCoverage {
+    pub fn lines_covered(&self) -> usize {
+        self.lines.iter().filter(|line| line.covered()).count()
+    }
+
+    pub fn functions_covered(&self) -> usize {
+        self.functions.iter().filter(|function| function.calls > 0).count()
+    }
+
+    pub fn branches_covered(&self) -> usize {
+        self.branches.iter().flat_map(|branch| &branch.hits).filter(|hits| **hits > 0).count()
+    }
+
+    pub fn branches_valid(&self) -> usize {
+        self.branches.iter().map(|branch| branch.hits.len()).sum()
+    }
+
+    /// The covered and total branches of each line with branch points
+    pub fn branches_by_line(&self) -> BTreeMap<usize, (usize, usize)> {
+        let mut by_line = BTreeMap::new();
+        for branch in &self.branches {
+            let (covered, valid) = by_line.entry(branch.line_number).or_insert((0, 0));
+            *covered += branch.hits.iter().filter(|hits| **hits > 0).count();
+            *valid += branch.hits.len();
+        }
+        by_line
+    }
+
+    pub fn line_rate(&self) -> f64 {
+        line_rate(self.lines_covered(), self.lines.len())
+    }
+
+    pub fn read_source(&self) -> Option<String> {
+        self.source.clone().or_else(|| util::fast_read(&self.path).ok())
+    }
+
+    /// Adds the coverage another bundle has of the same source file. Executable lines are the
+    /// union of both, and hits and calls are summed. A line is only partial when neither bundle
+    /// ran all of it.
+    pub fn merge(&mut self, other: FileCoverage) {
+        let mut lines: BTreeMap<usize, LineCoverage> = self.lines.drain(..).map(|line| (line.line_number, line)).collect();
+        for line in other.lines {
+            match lines.get_mut(&line.line_number) {
+                Some(ours) => {
+                    let partial = (ours.partial || !ours.covered()) && (line.partial || !line.covered());
+                    ours.hits += line.hits;
+                    ours.partial = partial && ours.covered();
+                }
+                None => {
+                    lines.insert(line.line_number, line);
+                }
+            }
+        }
+        self.lines = lines.into_values().collect();
+
+        for function in other.functions {
+            let ours = self
+                .functions
+                .iter_mut()
+                .find(|ours| ours.line_number == function.line_number && ours.name == function.name);
+            match ours {
+                Some(ours) => ours.calls += function.calls,
+                None => self.functions.push(function),
+            }
+        }
+        self.functions.sort_by_key(|function| function.line_number);
+
+        // Branch points on the same line are matched up in order
+        let mut seen: HashMap<usize, usize> = HashMap::new();
+        let mut added = vec![];
+        for branch in other.branches {
+            let nth = seen.entry(branch.line_number).or_insert(0);
+            let ours = self
+                .branches
+                .iter_mut()
+                .filter(|ours| ours.line_number == branch.line_number)
+                .nth(*nth);
+            *nth += 1;
+            match ours {
+                Some(ours) if ours.hits.len() == branch.hits.len() => {
+                    ours.hits.iter_mut().zip(branch.hits).for_each(|(ours, hits)| *ours += hits);
+                }
+                _ => added.push(branch),
+            }
+        }
+        self.branches.extend(added);
+        self.branches.sort_by_key(|branch| branch.line_number);
+
+        if self.source.is_none() {
+            self.source = other.source;
+        }
+    }
+}
+
+/// Merges the coverage of source files that are bundled into several scripts, so each file is
+/// reported once. Files keep the order they first appeared in.
+pub fn merge_files(files: Vec<FileCoverage>) -> Vec<FileCoverage> {
+    let mut merged: Vec<FileCoverage> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for file in files {
+        match positions.get(&file.path) {
+            Some(&position) => merged[position].merge(file),
+            None => {
+                positions.insert(file.path.clone(), merged.len());
+                merged.push(file);
+            }
+        }
+    }
+    merged
+}
+
+pub fn count_lines<'a>(files: impl IntoIterator<Item = &'a FileCoverage>) -> (usize, usize) {
+    files.into_iter().fold((0, 0), |(covered, valid), file| {
+        (covered + file.lines_covered(), valid + file.lines.len())
+    })
+}
+
+pub fn count_functions<'a>(files: impl IntoIterator<Item = &'a FileCoverage>) -> (usize, usize) {
+    files.into_iter().fold((0, 0), |(covered, valid), file| {
+        (covered + file.functions_covered(), valid + file.functions.len())
+    })
+}
+
+pub fn count_branches<'a>(files: impl IntoIterator<Item = &'a FileCoverage>) -> (usize, usize) {
+    files.into_iter().fold((0, 0), |(covered, valid), file| {
+        (covered + file.branches_covered(), valid + file.branches_valid())
+    })
+}
+
+pub fn line_rate(covered: usize, valid: usize) -> f64 {
+    if valid == 0 {
+        1.0
+    } else {
+        covered as f64 / valid as f64
+    }
+}
+
 pub struct ManyCoverage {
     pub files: Vec<FileCoverage>,
 }
 
 impl ManyCoverage {
-    pub fn write_xml<W: Write>(&self, writer: W) {
+    pub fn files_by_directory(&self) -> BTreeMap<String, Vec<&FileCoverage>> {
+        let mut directories: BTreeMap<String, Vec<&FileCoverage>> = BTreeMap::new();
+        for file in &self.files {
+            let directory = Path::new(&file.path)
+                .parent()
+                .map(|parent| parent.to_string_lossy().to_string())
+                .unwrap_or_default();
+            directories.entry(directory).or_default().push(file);
+        }
+        for files in directories.values_mut() {
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        directories
+    }
+
+    pub fn write_xml<W: Write>(&self, writer: W) -> io::Result<()> {
         let mut xml_writer = EventWriter::new(writer);
         xml_writer
             .write(XmlEvent::start_element("coverage").attr("version", "1"))
-            .unwrap();
+            .map_err(util::xml_error)?;
         for file in &self.files {
             xml_writer
                 .write(XmlEvent::start_element("file").attr("path", &file.path))
-                .unwrap();
+                .map_err(util::xml_error)?;
+            let branches = file.branches_by_line();
             for line in &file.lines {
-                xml_writer
-                    .write(
-                        XmlEvent::start_element("lineToCover")
-                            .attr("lineNumber", &format!("{}", line.line_number))
-                            .attr("covered", &format!("{}", line.covered)),
-                    ).unwrap();
-                xml_writer.write(XmlEvent::end_element()).unwrap();
+                let line_number = format!("{}", line.line_number);
+                let covered = format!("{}", line.covered());
+                let mut element = XmlEvent::start_element("lineToCover")
+                    .attr("lineNumber", &line_number)
+                    .attr("covered", &covered);
+                let branch_counts = branches.get(&line.line_number).map(|(covered, valid)| (format!("{}", valid), format!("{}", covered)));
+                if let Some((ref valid, ref covered)) = branch_counts {
+                    element = element.attr("branchesToCover", valid).attr("coveredBranches", covered);
+                }
+                xml_writer.write(element).map_err(util::xml_error)?;
+                xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
             }
-            xml_writer.write(XmlEvent::end_element()).unwrap();
+            xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
         }
-        xml_writer.write(XmlEvent::end_element()).unwrap();
+        xml_writer.write(XmlEvent::end_element()).map_err(util::xml_error)?;
 
-        xml_writer.into_inner().flush().expect("Could not flush xml file");
+        xml_writer.into_inner().flush()
     }
 
     #[allow(dead_code)]
-    pub fn write_xml_to_stdout(&self) {
+    pub fn write_xml_to_stdout(&self) -> io::Result<()> {
         let stdout = io::stdout();
         let handle = stdout.lock();
 
-        self.write_xml(handle);
+        self.write_xml(handle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::branches::BranchKind;
+    use crate::lines::{calculate_line_coverage, merge_files, BranchCoverage, BranchLocation, Coord, FileCoverage, FunctionCoverage, LineCoverage, LineState, SourceLines};
+    use crate::range::{Count, Range, RangeStack};
+    use crate::source_map::FilePath;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_line_coverage_after_multibyte_text() {
+        let cases = [
+            // Only `f();` ran, which starts at UTF-16 offset 18 (but byte offset 26)
+            ("var s=\"\u{1f600}\u{1f600}\u{1f600}\u{1f600}\";\nf();\ng();", 18, 22, vec![(1, 0), (2, 1), (3, 0)]),
+            // Only `g();` ran, which starts at UTF-16 offset 23 (but byte offset 26)
+            ("var s=\"h\u{e9}llo \u{1f600}\";\nf();\ng();", 23, 27, vec![(1, 0), (2, 0), (3, 1)]),
+        ];
+
+        for (minified, start, end, expected) in cases {
+            let ranges: RangeStack = Range::from_offsets(start, end, 1).into_iter().collect();
+
+            let mut lines = BTreeMap::new();
+            lines.insert(0, vec![Coord { line: 0, col: 0, end_col: None }]);
+            lines.insert(1, vec![Coord { line: 1, col: 0, end_col: None }]);
+            lines.insert(2, vec![Coord { line: 2, col: 0, end_col: None }]);
+            let line_refs = vec![Some(SourceLines { content: String::new(), lines, branches: vec![] })];
+
+            let files = vec![FilePath::Resolved(PathBuf::from("/a.js"))];
+            let coverage = calculate_line_coverage(ranges, line_refs, vec![], files, minified);
+            let hits = coverage[0].lines.iter().map(|line| (line.line_number, line.hits)).collect::<Vec<_>>();
+            assert_eq!(hits, expected);
+        }
+    }
+
+    #[test]
+    fn test_line_coverage_with_crlf_line_endings() {
+        let minified = "f();\r\ng();\r\nh();";
+        // Only `g();` ran, which starts at offset 6
+        let ranges: RangeStack = Range::from_offsets(6, 10, 1).into_iter().collect();
+
+        let mut lines = BTreeMap::new();
+        lines.insert(0, vec![Coord { line: 0, col: 0, end_col: None }]);
+        lines.insert(1, vec![Coord { line: 1, col: 0, end_col: None }]);
+        lines.insert(2, vec![Coord { line: 2, col: 0, end_col: None }]);
+        let line_refs = vec![Some(SourceLines { content: String::new(), lines, branches: vec![] })];
+
+        let files = vec![FilePath::Resolved(PathBuf::from("/a.js"))];
+        let coverage = calculate_line_coverage(ranges, line_refs, vec![], files, minified);
+        let hits = coverage[0].lines.iter().map(|line| (line.line_number, line.hits)).collect::<Vec<_>>();
+        assert_eq!(hits, vec![(1, 0), (2, 1), (3, 0)]);
+    }
+
+    #[test]
+    fn test_line_coverage_from_segment_spans() {
+        let minified = "if(a){b()}else{c()}\nd();";
+        // Everything ran except for the else block, `c()`
+        let ranges = RangeStack::from_nested(
+            Range::from_offsets(0, 24, 1).into_iter().chain(Range::from_offsets(14, 19, 0)),
+        );
+
+        let mut lines = BTreeMap::new();
+        // `if(a){` starts before the range, `b()}else{c()}` spans the uncovered block
+        lines.insert(0, vec![Coord { line: 0, col: 0, end_col: Some(6) }]);
+        lines.insert(1, vec![Coord { line: 0, col: 6, end_col: None }]);
+        lines.insert(2, vec![Coord { line: 0, col: 15, end_col: Some(18) }]);
+        lines.insert(3, vec![Coord { line: 1, col: 0, end_col: None }]);
+        let line_refs = vec![Some(SourceLines { content: String::new(), lines, branches: vec![] })];
+
+        let files = vec![FilePath::Resolved(PathBuf::from("/a.js"))];
+        let coverage = calculate_line_coverage(ranges, line_refs, vec![], files, minified);
+        let states = coverage[0].lines.iter().map(|line| line.state()).collect::<Vec<_>>();
+        assert_eq!(states, vec![LineState::Covered, LineState::Partial, LineState::Uncovered, LineState::Covered]);
+    }
+
+    #[test]
+    fn test_branch_coverage() {
+        let minified = "if(a){b()}c(a?1:2);";
+        // The if block never ran, and the ternary only took its second branch
+        let ranges = RangeStack::from_nested(
+            Range::from_offsets(0, 19, 1)
+                .into_iter()
+                .chain(Range::from_offsets(5, 10, 0))
+                .chain(Range::from_offsets(14, 15, 0)),
+        );
+
+        let mut lines = BTreeMap::new();
+        lines.insert(0, vec![Coord { line: 0, col: 0, end_col: Some(10) }]);
+        lines.insert(3, vec![Coord { line: 0, col: 10, end_col: None }]);
+        let branches = vec![
+            BranchLocation { kind: BranchKind::If, line: 0, point: (0, 0), arms: vec![Some((0, 6))], implicit_else: true },
+            BranchLocation { kind: BranchKind::Ternary, line: 4, point: (0, 12), arms: vec![Some((0, 14)), Some((0, 16)), None], implicit_else: false },
+        ];
+        let line_refs = vec![Some(SourceLines { content: String::new(), lines, branches })];
+
+        let files = vec![FilePath::Resolved(PathBuf::from("/a.js"))];
+        let coverage = calculate_line_coverage(ranges, line_refs, vec![], files, minified);
+        let hits = coverage[0].branches.iter().map(|branch| (branch.line_number, branch.hits.clone())).collect::<Vec<_>>();
+        assert_eq!(hits, vec![(1, vec![0, 1]), (5, vec![0, 1, 0])]);
+        assert_eq!((coverage[0].branches_covered(), coverage[0].branches_valid()), (2, 5));
+
+        // The ternary on the continuation line makes that line executable, it's reached once
+        let by_line = coverage[0].branches_by_line().into_iter().collect::<Vec<_>>();
+        assert_eq!(by_line, vec![(1, (1, 2)), (5, (1, 3))]);
+        let lines = coverage[0].lines.iter().map(|line| (line.line_number, line.hits)).collect::<Vec<_>>();
+        assert_eq!(lines, vec![(1, 1), (4, 1), (5, 1)]);
+    }
+
+    #[test]
+    fn test_merge_files() {
+        let file = |path: &str, lines: &[(usize, Count, bool)], calls, branch_hits: Vec<Count>| FileCoverage {
+            path: path.to_owned(),
+            lines: lines.iter().map(|&(line_number, hits, partial)| LineCoverage { line_number, hits, partial }).collect(),
+            functions: vec![FunctionCoverage { name: "add".to_owned(), line_number: 1, calls }],
+            branches: vec![BranchCoverage { kind: BranchKind::If, line_number: 2, hits: branch_hits }],
+            source: None,
+        };
+        let files = vec![
+            file("/src/util.js", &[(2, 1, true), (3, 0, false)], 1, vec![1, 0]),
+            file("/src/main.js", &[(1, 1, false)], 0, vec![0, 0]),
+            file("/src/util.js", &[(2, 2, false), (3, 0, false), (5, 1, false)], 2, vec![0, 3]),
+        ];
+
+        let merged = merge_files(files);
+        assert_eq!(merged.iter().map(|file| file.path.as_str()).collect::<Vec<_>>(), vec!["/src/util.js", "/src/main.js"]);
+        let util = &merged[0];
+        let lines = util.lines.iter().map(|line| (line.line_number, line.hits, line.state())).collect::<Vec<_>>();
+        assert_eq!(lines, vec![(2, 3, LineState::Covered), (3, 0, LineState::Uncovered), (5, 1, LineState::Covered)]);
+        assert_eq!(util.functions.len(), 1);
+        assert_eq!(util.functions[0].calls, 3);
+        assert_eq!(util.branches.len(), 1);
+        assert_eq!(util.branches[0].hits, vec![1, 3]);
     }
 }
@@ -1,5 +1,7 @@
 use std::fmt;
-use crate::model::PuppeteerData;
+use crate::model::{FunctionRange, PuppeteerData, ScriptCoverage, SourceMapCacheEntry};
+use serde::de::IgnoredAny;
+use serde::de::MapAccess;
 use serde::de::SeqAccess;
 use serde::de::Visitor;
 use serde::Deserializer;
@@ -8,14 +10,16 @@ use std::collections::HashMap;
 
 use crate::load;
 use crate::model;
-use crate::range::Range;
+use crate::range::RangeStack;
 use std::path::Path;
 use crate::util;
 use serde_json::Error;
 
 pub struct RawCoveragePart {
     text: String,
-    ranges: Vec<Range>,
+    ranges: RangeStack,
+    source_map_url: Option<String>,
+    functions: Vec<FunctionRange>,
 }
 pub type RawCoverage = HashMap<String, RawCoveragePart>;
 
@@ -46,34 +50,82 @@ pub fn load_items<P: AsRef<Path>>(paths: Vec<P>) -> Loader{
     loader
 }
 
+/// The entries of a coverage list, as written by puppeteer or by playwright
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CoverageEntry {
+    Puppeteer(PuppeteerData),
+    Playwright(ScriptCoverage),
+}
+
 pub struct Loader {
     parts: RawCoverage,
+    source_maps: HashMap<String, SourceMapCacheEntry>,
 }
 
 struct VisitorAppender<'a> {
     parts: &'a mut RawCoverage,
+    source_maps: &'a mut HashMap<String, SourceMapCacheEntry>,
 }
 
 impl<'a> VisitorAppender<'a> {
     #[inline]
-    fn add_data(&mut self, mut data: PuppeteerData) {
-        let inserted = if let Some(existing_data) = self.parts.get_mut(&data.url) {
-            existing_data.ranges.append(&mut data.ranges.ranges);
-            true
-        } else {
-            false
-        };
+    fn add_data(&mut self, data: PuppeteerData) {
+        // Each entry is a separate run, so the counts of the runs add up
+        let ranges: RangeStack = data.ranges.ranges.iter().collect();
 
-        if !inserted {
+        if let Some(existing_data) = self.parts.get_mut(&data.url) {
+            existing_data.ranges = existing_data.ranges.sum(&ranges);
+            if existing_data.text.is_empty() {
+                existing_data.text = data.text;
+            }
+            if existing_data.source_map_url.is_none() {
+                existing_data.source_map_url = data.source_map_url;
+            }
+            for function in data.functions {
+                let existing_function = existing_data
+                    .functions
+                    .iter_mut()
+                    .find(|existing| existing.start == function.start && existing.end == function.end);
+                match existing_function {
+                    Some(existing_function) => existing_function.count += function.count,
+                    None => existing_data.functions.push(function),
+                }
+            }
+        } else {
             self.parts.insert(
                 data.url,
                 RawCoveragePart {
                     text: data.text,
-                    ranges: data.ranges.ranges,
+                    ranges,
+                    source_map_url: data.source_map_url,
+                    functions: data.functions,
                 },
             );
         }
     }
+
+    #[inline]
+    fn add_script_coverage(&mut self, script: ScriptCoverage) {
+        // Scripts without a url (evals, devtools snippets) or with a scheme-less one (node's own
+        // internals) can't be traced back to a bundle
+        if !script.url.contains("://") {
+            return;
+        }
+
+        let ranges = script.block_ranges();
+        let functions = script.function_ranges();
+        // The devtools protocol doesn't include the script source (playwright does), when it's
+        // missing it's read from disk later
+        self.add_data(PuppeteerData {
+            url: script.url,
+            text: script.source.unwrap_or_default(),
+            ranges,
+            source_map_url: script.source_map_url,
+            source_map: None,
+            functions,
+        });
+    }
 }
 
 impl Loader {
@@ -81,6 +133,7 @@ impl Loader {
     pub fn new() -> Loader {
         Loader {
             parts: HashMap::new(),
+            source_maps: HashMap::new(),
         }
     }
 
@@ -91,29 +144,34 @@ impl Loader {
     ) -> Result<(), Error>{
         let visitor: VisitorAppender<'_> = VisitorAppender {
             parts: &mut self.parts,
+            source_maps: &mut self.source_maps,
         };
-        Ok(deserializer.deserialize_seq(visitor)?)
-    }
-}
-
-fn convert_to_puppeteer_data((key, value): (String, RawCoveragePart)) -> PuppeteerData {
-    PuppeteerData {
-        url: key,
-        text: value.text,
-        ranges: value.ranges.into_iter().collect(),
+        Ok(deserializer.deserialize_any(visitor)?)
     }
 }
 
 impl IntoIterator for Loader {
     type Item = PuppeteerData;
-    // This is SOOOOOOOO ugly, but at least usable
-    type IntoIter = ::std::iter::Map<
-        ::std::collections::hash_map::IntoIter<::std::string::String, load::RawCoveragePart>,
-        fn((String, load::RawCoveragePart)) -> model::PuppeteerData,
-    >;
+    type IntoIter = ::std::vec::IntoIter<model::PuppeteerData>;
 
     fn into_iter(self) -> <Self as IntoIterator>::IntoIter {
-        self.parts.into_iter().map(convert_to_puppeteer_data)
+        let mut source_maps = self.source_maps;
+        self.parts
+            .into_iter()
+            .map(|(key, value)| {
+                let cached = source_maps.remove(&key);
+                PuppeteerData {
+                    // Where node found the map, its sources are relative to it
+                    source_map_url: value.source_map_url.or_else(|| cached.as_ref().and_then(|entry| entry.url.clone())),
+                    source_map: cached.and_then(|entry| entry.data),
+                    url: key,
+                    text: value.text,
+                    ranges: value.ranges,
+                    functions: value.functions,
+                }
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
     }
 }
 
@@ -121,7 +179,7 @@ impl<'va, 'de: 'va> Visitor<'de> for VisitorAppender<'va> {
     type Value = ();
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        write!(formatter, "expected a list of puppeteer coverage data")
+        write!(formatter, "expected a list of puppeteer or playwright coverage data, or devtools precise coverage data")
     }
 
     fn visit_seq<S>(self, mut seq: S) -> Result<(), S::Error>
@@ -131,7 +189,35 @@ impl<'va, 'de: 'va> Visitor<'de> for VisitorAppender<'va> {
         let mut collapser = self;
 
         while let Some(value) = seq.next_element()? {
-            collapser.add_data(value);
+            match value {
+                CoverageEntry::Puppeteer(data) => collapser.add_data(data),
+                CoverageEntry::Playwright(script) => collapser.add_script_coverage(script),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visit_map<M>(self, mut map: M) -> Result<(), M::Error>
+    where
+        M: MapAccess<'de>,
+    {
+        let mut collapser = self;
+
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "result" {
+                for script in map.next_value::<Vec<ScriptCoverage>>()? {
+                    collapser.add_script_coverage(script);
+                }
+            } else if key == "source-map-cache" {
+                for (url, entry) in map.next_value::<HashMap<String, SourceMapCacheEntry>>()? {
+                    if entry.data.is_some() {
+                        collapser.source_maps.insert(url, entry);
+                    }
+                }
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
         }
 
         Ok(())
@@ -188,10 +274,20 @@ mod test {
                 "url": "1",
                 "ranges": [{
                     "start": 0,
-                    "end": 2
+                    "end": 0,
+                    "count": 1
+                },{
+                    "start": 1,
+                    "end": 2,
+                    "count": 2
                 },{
                     "start": 4,
-                    "end": 7
+                    "end": 5,
+                    "count": 2
+                },{
+                    "start": 6,
+                    "end": 7,
+                    "count": 1
                 }]
             },
             {
@@ -220,4 +316,152 @@ mod test {
 
         assert_eq!(combined, loaded)
     }
+
+    #[test]
+    fn test_load_precise_coverage() {
+        let data = r#"{
+            "result": [
+                {
+                    "scriptId": "12",
+                    "url": "http://localhost/main.js",
+                    "functions": [
+                        {
+                            "functionName": "",
+                            "isBlockCoverage": false,
+                            "ranges": [{ "startOffset": 0, "endOffset": 100, "count": 1 }]
+                        },
+                        {
+                            "functionName": "called",
+                            "isBlockCoverage": true,
+                            "ranges": [
+                                { "startOffset": 10, "endOffset": 50, "count": 3 },
+                                { "startOffset": 20, "endOffset": 30, "count": 0 }
+                            ]
+                        }
+                    ]
+                },
+                {
+                    "scriptId": "13",
+                    "url": "",
+                    "functions": []
+                }
+            ]
+        }"#;
+
+        let mut deserializer = JsonDeserializer::from_slice(data.as_bytes());
+        let mut loader = Loader::new();
+
+        loader.add_json_data(&mut deserializer).unwrap();
+
+        let loaded = loader.into_iter().collect::<Vec<PuppeteerData>>();
+        assert_eq!(loaded.len(), 1);
+
+        let script = &loaded[0];
+        assert_eq!(script.url, "http://localhost/main.js");
+        assert_eq!(script.text, "");
+        assert_eq!(script.ranges.count_at(5), 1);
+        assert_eq!(script.ranges.count_at(15), 3);
+        assert_eq!(script.ranges.count_at(25), 0);
+        assert_eq!(script.ranges.count_at(45), 3);
+        assert_eq!(script.ranges.count_at(75), 1);
+    }
+
+    #[test]
+    fn test_load_node_coverage() {
+        let data = r#"{
+            "result": [
+                {
+                    "scriptId": "7",
+                    "url": "node:internal/main/run_main_module",
+                    "functions": []
+                },
+                {
+                    "scriptId": "80",
+                    "url": "file:///srv/app/dist/server.js",
+                    "functions": [
+                        {
+                            "functionName": "",
+                            "isBlockCoverage": true,
+                            "ranges": [{ "startOffset": 0, "endOffset": 40, "count": 1 }]
+                        }
+                    ]
+                }
+            ],
+            "timestamp": 1234.5,
+            "source-map-cache": {
+                "file:///srv/app/dist/server.js": {
+                    "lineLengths": [40],
+                    "url": "file:///srv/app/dist/server.js.map",
+                    "data": {
+                        "version": 3,
+                        "file": "server.js",
+                        "sourceRoot": "",
+                        "sources": ["../src/server.ts"],
+                        "names": [],
+                        "mappings": "AAAA"
+                    }
+                }
+            }
+        }"#;
+
+        let mut deserializer = JsonDeserializer::from_slice(data.as_bytes());
+        let mut loader = Loader::new();
+
+        loader.add_json_data(&mut deserializer).unwrap();
+
+        let loaded = loader.into_iter().collect::<Vec<PuppeteerData>>();
+        assert_eq!(loaded.len(), 1);
+
+        let script = &loaded[0];
+        assert_eq!(script.url, "file:///srv/app/dist/server.js");
+        assert_eq!(script.ranges.count_at(39), 1);
+        let source_map = script.source_map.as_ref().unwrap();
+        assert_eq!(source_map["sources"][0], "../src/server.ts");
+        assert_eq!(script.source_map_url.as_deref(), Some("file:///srv/app/dist/server.js.map"));
+    }
+
+    #[test]
+    fn test_load_playwright_coverage() {
+        let data = r#"[
+            {
+                "url": "http://localhost/main.js",
+                "scriptId": "4",
+                "source": "function a(){}\na();",
+                "functions": [
+                    {
+                        "functionName": "",
+                        "isBlockCoverage": false,
+                        "ranges": [{ "startOffset": 0, "endOffset": 19, "count": 1 }]
+                    },
+                    {
+                        "functionName": "a",
+                        "isBlockCoverage": true,
+                        "ranges": [{ "startOffset": 0, "endOffset": 14, "count": 2 }]
+                    }
+                ]
+            },
+            {
+                "text": "other();",
+                "url": "http://localhost/other.js",
+                "ranges": [{ "start": 0, "end": 7 }]
+            }
+        ]"#;
+
+        let mut deserializer = JsonDeserializer::from_slice(data.as_bytes());
+        let mut loader = Loader::new();
+
+        loader.add_json_data(&mut deserializer).unwrap();
+
+        let mut loaded = loader.into_iter().collect::<Vec<PuppeteerData>>();
+        loaded.sort_by_key(|v| v.url.clone());
+        assert_eq!(loaded.len(), 2);
+
+        assert_eq!(loaded[0].url, "http://localhost/main.js");
+        assert_eq!(loaded[0].text, "function a(){}\na();");
+        assert_eq!(loaded[0].ranges.count_at(3), 2);
+        assert_eq!(loaded[0].ranges.count_at(16), 1);
+
+        assert_eq!(loaded[1].url, "http://localhost/other.js");
+        assert_eq!(loaded[1].ranges.count_at(3), 1);
+    }
 }
@@ -1,6 +1,8 @@
 //use ::serde_derive::{Serialize, Deserialize};
 
-use crate::range::RangeStack;
+use crate::range::{Count, Range, RangeStack};
+use serde_json::Value;
+use std::path::PathBuf;
 
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct PuppeteerData {
@@ -9,27 +11,268 @@ pub struct PuppeteerData {
 
     #[serde(flatten)]
     pub ranges: RangeStack,
+
+    /// Where the source map lives when the bundle doesn't say, e.g. from a `SourceMap` header
+    #[serde(default, rename = "sourceMapURL", skip_serializing_if = "Option::is_none")]
+    pub source_map_url: Option<String>,
+
+    /// A source map that came along with the coverage data, e.g. from node's source map cache
+    #[serde(skip)]
+    pub source_map: Option<Value>,
+
+    /// The functions V8 reported, puppeteer's coverage doesn't have them
+    #[serde(skip)]
+    pub functions: Vec<FunctionRange>,
+}
+
+/// A function in the generated script and the number of times it was called
+#[derive(PartialEq, Debug, Clone)]
+pub struct FunctionRange {
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+    pub count: Count,
+}
+
+/// An entry of the `source-map-cache` node writes next to the coverage in `NODE_V8_COVERAGE`
+#[derive(Debug, Deserialize)]
+pub struct SourceMapCacheEntry {
+    /// Where the map was found
+    #[serde(default)]
+    pub url: Option<String>,
+    pub data: Option<Value>,
+}
+
+/// A script entry from the `result` of the devtools protocol's `Profiler.takePreciseCoverage`.
+/// Playwright's `stopJSCoverage` returns the same entries, along with the script `source`.
+#[derive(Debug, Deserialize)]
+pub struct ScriptCoverage {
+    pub url: String,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default, rename = "sourceMapURL")]
+    pub source_map_url: Option<String>,
+    pub functions: Vec<ScriptFunction>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScriptFunction {
+    #[serde(default, rename = "functionName")]
+    pub function_name: String,
+    pub ranges: Vec<BlockRange>,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+pub struct BlockRange {
+    pub startOffset: usize,
+    pub endOffset: usize,
+    pub count: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[allow(non_snake_case)]
 pub struct SourceMap {
     pub version: u32,
-    pub file: String,
-    pub sourceRoot: String,
+    #[serde(default)]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub sourceRoot: Option<String>,
     pub sources: Vec<String>,
     pub sourcesContent: Option<Vec<Option<String>>>,
+    #[serde(default)]
     pub names: Vec<String>,
     pub mappings: String,
+
+    /// The directory the map was read from, which relative sources are resolved against
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+/// A source map made of `sections` that each bring their own map, as written for concatenated bundles
+#[derive(Debug, Deserialize)]
+pub struct IndexSourceMap {
+    #[serde(default)]
+    pub file: Option<String>,
+    pub sections: Vec<SourceMapSection>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SourceMapSection {
+    pub offset: SectionOffset,
+    #[serde(default)]
+    pub map: Option<Value>,
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// Where a section starts in the generated file, 0 based
+#[derive(Debug, Deserialize)]
+pub struct SectionOffset {
+    pub line: i64,
+    pub column: i64,
+}
+
+impl ScriptCoverage {
+    pub fn block_ranges(&self) -> RangeStack {
+        RangeStack::from_nested(self.functions.iter().flat_map(|function| {
+            function
+                .ranges
+                .iter()
+                .flat_map(|range| Range::from_offsets(range.startOffset, range.endOffset, range.count))
+        }))
+    }
+
+    /// The functions of the script, the first range of each spanning the whole function. The
+    /// script's own top level is reported as a nameless function starting at 0, which is left out.
+    pub fn function_ranges(&self) -> Vec<FunctionRange> {
+        self.functions
+            .iter()
+            .filter_map(|function| {
+                let range = function.ranges.first()?;
+                if function.function_name.is_empty() && range.startOffset == 0 {
+                    return None;
+                }
+                Some(FunctionRange {
+                    name: function.function_name.clone(),
+                    start: range.startOffset,
+                    end: range.endOffset,
+                    count: range.count,
+                })
+            })
+            .collect()
+    }
+}
+
+impl SourceMap {
+    /// The `sources`, with the `sourceRoot` prepended to the ones that aren't absolute urls
+    pub fn source_urls(&self) -> Vec<String> {
+        let root = self.sourceRoot.as_deref().unwrap_or("");
+        self.sources
+            .iter()
+            .map(|source| {
+                if root.is_empty() || source.contains("://") {
+                    source.clone()
+                } else if root.ends_with('/') {
+                    format!("{}{}", root, source)
+                } else {
+                    format!("{}/{}", root, source)
+                }
+            })
+            .collect()
+    }
+}
+
+fn is_line_terminator(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{2028}' | '\u{2029}')
+}
+
+/// The url from the body of a `# sourceMappingURL=...` (or legacy `@ sourceMappingURL=...`) comment
+fn source_mapping_pragma(comment: &str) -> Option<String> {
+    let comment = comment.strip_prefix('#').or_else(|| comment.strip_prefix('@'))?;
+    let url = comment.trim_start().strip_prefix("sourceMappingURL=")?;
+    let url = url.split_whitespace().next()?;
+    Some(url.to_owned())
 }
 
 impl PuppeteerData {
     pub fn get_source_mapping_url(&self) -> Option<String> {
-        let last_part = self.text.lines().last().unwrap_or("No Content").to_owned();
-        if last_part.starts_with("//# sourceMappingURL=") {
-            Some(last_part.replace("//# sourceMappingURL=", ""))
+        find_source_mapping_url(&self.text)
+    }
+}
+
+/// Finds the `sourceMappingURL` comment at the end of a script. Only comments and whitespace
+/// may follow it, so the scan goes backwards over those and stops at the first code it sees.
+pub fn find_source_mapping_url(text: &str) -> Option<String> {
+    let mut lines = text.rsplit(is_line_terminator);
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (code, comment) = if let Some(body) = line.strip_suffix("*/") {
+            match body.rfind("/*") {
+                Some(start) => (&body[..start], Some(&body[start + 2..])),
+                None => {
+                    // A comment spanning several lines can't hold the url, skip to its start
+                    let opening = lines.find(|line| line.contains("/*"))?;
+                    (&opening[..opening.rfind("/*").unwrap()], None)
+                }
+            }
+        } else if let Some(comment) = line.strip_prefix("//") {
+            ("", Some(comment))
         } else {
-            None
+            // Code, possibly with the comment right after it: `})();//# sourceMappingURL=main.js.map`
+            // Looking for `//#` (or `//@`) rather than `//`, which can be part of the url itself
+            let start = line.rfind("//#").max(line.rfind("//@"));
+            return start.and_then(|start| source_mapping_pragma(&line[start + 2..]));
+        };
+
+        if let Some(url) = comment.and_then(source_mapping_pragma) {
+            return Some(url);
+        }
+        if !code.trim().is_empty() {
+            return None;
         }
     }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use crate::model::{PuppeteerData, SourceMap};
+    use crate::range::RangeStack;
+
+    fn source_mapping_url(text: &str) -> Option<String> {
+        PuppeteerData {
+            url: "http://localhost/main.js".to_owned(),
+            text: text.to_owned(),
+            ranges: RangeStack { ranges: vec![] },
+            source_map_url: None,
+            source_map: None,
+            functions: vec![],
+        }
+        .get_source_mapping_url()
+    }
+
+    #[test]
+    fn test_get_source_mapping_url() {
+        let url = Some("main.js.map".to_owned());
+        assert_eq!(source_mapping_url("a();\n//# sourceMappingURL=main.js.map"), url);
+        assert_eq!(source_mapping_url("a();\n//# sourceMappingURL=main.js.map\n\n  \n"), url);
+        assert_eq!(source_mapping_url("a();\r\n//@ sourceMappingURL=main.js.map\r\n"), url);
+        assert_eq!(source_mapping_url("a();\n/*# sourceMappingURL=main.js.map */"), url);
+        assert_eq!(source_mapping_url("a();//# sourceMappingURL=main.js.map"), url);
+        assert_eq!(
+            source_mapping_url("a();\n//# sourceMappingURL=main.js.map\n// @license MIT\n/*!\n * b v1.0\n */\n"),
+            url
+        );
+
+        let absolute = Some("https://cdn.example.com/main.js.map".to_owned());
+        assert_eq!(source_mapping_url("})();//# sourceMappingURL=https://cdn.example.com/main.js.map"), absolute);
+        assert_eq!(source_mapping_url("a();\n//# sourceMappingURL=https://cdn.example.com/main.js.map\n"), absolute);
+        assert_eq!(source_mapping_url("a();//@ sourceMappingURL=https://cdn.example.com/main.js.map"), absolute);
+    }
+
+    #[test]
+    fn test_get_source_mapping_url_stops_at_code() {
+        assert_eq!(source_mapping_url("a();"), None);
+        assert_eq!(source_mapping_url("//# sourceMappingURL=main.js.map\na();\n"), None);
+        assert_eq!(source_mapping_url("//# sourceMappingURL=main.js.map\na(); /* done */\n"), None);
+        assert_eq!(source_mapping_url("a();\n//# sourceMappingURL=\n"), None);
+    }
+
+    #[test]
+    fn test_source_urls() {
+        let source_map: SourceMap = ::serde_json::from_str(
+            r#"{"version":3,"sourceRoot":"../src","sources":["a.js","webpack:///b.js"],"mappings":""}"#,
+        ).unwrap();
+        assert_eq!(source_map.file, None);
+        assert_eq!(source_map.source_urls(), vec!["../src/a.js", "webpack:///b.js"]);
+
+        let source_map: SourceMap =
+            ::serde_json::from_str(r#"{"version":3,"sources":["a.js"],"names":[],"mappings":""}"#).unwrap();
+        assert_eq!(source_map.source_urls(), vec!["a.js"]);
+    }
 }
@@ -0,0 +1,127 @@
+/// Whether a line ends with `c`. Source maps break lines on `\n`, `\r\n`, a lone `\r`, and the
+/// unicode line and paragraph separators.
+pub fn is_line_break(c: char, next: Option<char>) -> bool {
+    match c {
+        '\r' => next != Some('\n'),
+        '\n' | '\u{2028}' | '\u{2029}' => true,
+        _ => false,
+    }
+}
+
+/// Splits text into lines the way source maps count them, unlike `str::lines`
+pub fn split_lines(text: &str) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        if is_line_break(c, chars.peek().map(|(_, next)| *next)) {
+            let line = &text[start..idx];
+            lines.push(if c == '\n' { line.strip_suffix('\r').unwrap_or(line) } else { line });
+            start = idx + c.len_utf8();
+        }
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+    lines
+}
+
+/// Converts between the offsets V8 reports for a script and line/column positions in it. Both
+/// count UTF-16 code units, like source map columns do, rather than the bytes of the `str`.
+pub struct OffsetIndex {
+    /// The offset each line starts at
+    line_starts: Vec<usize>,
+    /// The offset each line's terminator starts at, or the end of the text for the last line
+    line_ends: Vec<usize>,
+}
+
+impl OffsetIndex {
+    pub fn new(text: &str) -> OffsetIndex {
+        let mut line_starts = vec![0];
+        let mut line_ends = vec![];
+        let mut offset = 0;
+        let mut previous = None;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if is_line_break(c, chars.peek().cloned()) {
+                let crlf = c == '\n' && previous == Some('\r');
+                line_ends.push(if crlf { offset - 1 } else { offset });
+                line_starts.push(offset + c.len_utf16());
+            }
+            offset += c.len_utf16();
+            previous = Some(c);
+        }
+        line_ends.push(offset);
+        OffsetIndex { line_starts, line_ends }
+    }
+
+    /// The offset of a 0 based line and column, or `None` past the last line
+    pub fn offset(&self, line: usize, column: usize) -> Option<usize> {
+        self.line_starts.get(line).map(|start| start + column)
+    }
+
+    /// The offset a 0 based line ends at, not counting its line terminator
+    pub fn line_end(&self, line: usize) -> Option<usize> {
+        self.line_ends.get(line).cloned()
+    }
+
+    /// The 0 based line and column of an offset
+    #[allow(dead_code)]
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        (line, offset - self.line_starts[line])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::offsets::{split_lines, OffsetIndex};
+
+    #[test]
+    fn test_ascii_offsets() {
+        let index = OffsetIndex::new("ab\ncd\n\nef");
+
+        assert_eq!(index.offset(0, 1), Some(1));
+        assert_eq!(index.offset(1, 0), Some(3));
+        assert_eq!(index.offset(3, 1), Some(8));
+        assert_eq!(index.offset(4, 0), None);
+        assert_eq!(index.position(4), (1, 1));
+        assert_eq!(index.position(6), (2, 0));
+    }
+
+    #[test]
+    fn test_multibyte_offsets() {
+        // é is 2 bytes and 1 UTF-16 unit, the emoji is 4 bytes and 2 units (a surrogate pair)
+        let index = OffsetIndex::new("s = \"é😀\";\nf();\n😀;g();");
+
+        assert_eq!(index.offset(1, 0), Some(11));
+        assert_eq!(index.offset(2, 3), Some(19));
+        assert_eq!(index.position(11), (1, 0));
+        assert_eq!(index.position(19), (2, 3));
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let index = OffsetIndex::new("a\r\nb\rc\nd\u{2028}e\u{2029}f\r\n");
+
+        assert_eq!(index.offset(1, 0), Some(3));
+        assert_eq!(index.offset(2, 0), Some(5));
+        assert_eq!(index.offset(3, 0), Some(7));
+        assert_eq!(index.offset(4, 0), Some(9));
+        assert_eq!(index.offset(5, 0), Some(11));
+        assert_eq!(index.position(2), (0, 2));
+        assert_eq!(index.position(10), (4, 1));
+        assert_eq!(index.line_end(0), Some(1));
+        assert_eq!(index.line_end(1), Some(4));
+        assert_eq!(index.line_end(5), Some(12));
+        assert_eq!(index.line_end(6), Some(14));
+    }
+
+    #[test]
+    fn test_split_lines() {
+        assert_eq!(split_lines("a\r\nb\rc\nd\u{2028}e\u{2029}f\r\n"), vec!["a", "b", "c", "d", "e", "f"]);
+        assert_eq!(split_lines("a\n\r\n\rb"), vec!["a", "", "", "b"]);
+        assert_eq!(split_lines("\u{e9}\r"), vec!["\u{e9}"]);
+        assert!(split_lines("").is_empty());
+    }
+}
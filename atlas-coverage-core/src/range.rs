@@ -1,16 +1,33 @@
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::iter::FromIterator;
 use std::{cmp, fmt};
 
 type Value = usize;
+pub type Count = u64;
+
+fn default_count() -> Count {
+    1
+}
 
 #[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Range {
     start: Value,
     end: Value,
+    #[serde(default = "default_count")]
+    count: Count,
 }
 
 impl Range {
+    /// Builds a range from V8's half open `[start_offset, end_offset)` block offsets
+    pub fn from_offsets(start_offset: Value, end_offset: Value, count: Count) -> Option<Range> {
+        if end_offset > start_offset {
+            Some(Range { start: start_offset, end: end_offset - 1, count })
+        } else {
+            None
+        }
+    }
+
     fn overlaps(&self, other: &Range) -> bool {
         (other.start >= self.start && other.start <= self.end)
             || (other.end >= self.start && other.end <= self.end)
@@ -19,6 +36,7 @@ impl Range {
     fn merge(&mut self, other: &Range) {
         self.start = cmp::min(self.start, other.start);
         self.end = cmp::max(self.end, other.end);
+        self.count = cmp::max(self.count, other.count);
     }
 }
 
@@ -38,7 +56,7 @@ impl Range {
 
 impl fmt::Display for Range {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[{},{}]", self.start, self.end)
+        write!(f, "[{},{}]x{}", self.start, self.end, self.count)
     }
 }
 
@@ -60,9 +78,104 @@ impl RangeStack {
     }
 
     pub fn contains_value(&self, value: Value) -> bool {
+        self.count_at(value) > 0
+    }
+
+    pub fn count_at(&self, value: Value) -> Count {
         self.ranges
-            .binary_search_by(|ref range| range.compare_to_item(value))
-            .is_ok()
+            .binary_search_by(|range| range.compare_to_item(value))
+            .map(|idx| self.ranges[idx].count)
+            .unwrap_or(0)
+    }
+
+    /// The lowest and highest count over the half open span `[start, end)`
+    pub fn count_range(&self, start: Value, end: Value) -> (Count, Count) {
+        let end = cmp::max(end, start + 1);
+        let first = self.ranges.partition_point(|range| range.end < start);
+
+        let (mut lowest, mut highest) = (None, 0);
+        let mut position = start;
+        for range in self.ranges[first..].iter().take_while(|range| range.start < end) {
+            if range.start > position {
+                lowest = Some(0);
+            }
+            lowest = Some(lowest.map_or(range.count, |lowest: Count| lowest.min(range.count)));
+            highest = highest.max(range.count);
+            position = range.end + 1;
+        }
+        if position < end {
+            lowest = Some(0);
+        }
+
+        (lowest.unwrap_or(0), highest)
+    }
+
+    /// Adds up the counts of two range stacks, e.g. to combine the coverage of separate runs
+    pub fn sum(&self, other: &RangeStack) -> RangeStack {
+        // Counts starting and ending at each offset, with ends being exclusive
+        let mut changes: BTreeMap<Value, (Count, Count)> = BTreeMap::new();
+        for range in self.ranges.iter().chain(other.ranges.iter()) {
+            changes.entry(range.start).or_insert((0, 0)).0 += range.count;
+            changes.entry(range.end + 1).or_insert((0, 0)).1 += range.count;
+        }
+
+        let mut range_stack = RangeStack { ranges: Vec::new() };
+        let mut count = 0;
+        let changes: Vec<_> = changes.into_iter().collect();
+        for window in changes.windows(2) {
+            let ((start, (started, ended)), (end, _)) = (window[0], window[1]);
+            count = count + started - ended;
+            if count > 0 {
+                range_stack.push_segment(start, end, count);
+            }
+        }
+
+        range_stack
+    }
+
+    /// Appends the half open segment `[start, end)`, extending the last range if they line up
+    fn push_segment(&mut self, start: Value, end: Value, count: Count) {
+        match self.ranges.last_mut() {
+            Some(last) if last.end + 1 == start && last.count == count => last.end = end - 1,
+            _ => self.ranges.push(Range { start, end: end - 1, count }),
+        }
+    }
+
+    /// Flattens V8 block coverage into disjoint ranges. V8 reports ranges nested inside each
+    /// other, where the innermost range containing an offset determines its count, so e.g. a
+    /// count 0 block inside a called function carves an uncovered hole out of it.
+    pub fn from_nested<I>(iterator: I) -> RangeStack
+    where
+        I: IntoIterator<Item = Range>,
+    {
+        let mut raw_ranges: Vec<_> = iterator.into_iter().collect();
+        // Containing ranges sort before the ranges nested inside of them
+        raw_ranges.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+
+        // Maps the start of every painted segment to its count, the segment running up to the
+        // next key. Offsets are half open here to make splitting segments straightforward.
+        let mut painted: BTreeMap<Value, Count> = BTreeMap::new();
+        for range in &raw_ranges {
+            let (start, end) = (range.start, range.end + 1);
+            let count_after = painted.range(..=end).next_back().map(|(_, count)| *count).unwrap_or(0);
+            let overwritten: Vec<_> = painted.range(start..end).map(|(offset, _)| *offset).collect();
+            for offset in overwritten {
+                painted.remove(&offset);
+            }
+            painted.insert(start, range.count);
+            painted.entry(end).or_insert(count_after);
+        }
+
+        let mut range_stack = RangeStack { ranges: Vec::new() };
+        let segments: Vec<_> = painted.into_iter().collect();
+        for window in segments.windows(2) {
+            let ((start, count), (end, _)) = (window[0], window[1]);
+            if count > 0 {
+                range_stack.push_segment(start, end, count);
+            }
+        }
+
+        range_stack
     }
 }
 
@@ -104,12 +217,12 @@ impl<'a> FromIterator<&'a Range> for RangeStack {
 
 #[cfg(test)]
 mod test {
-    use super::Range;
+    use super::{Range, RangeStack};
     use std::cmp::Ordering;
 
     #[test]
     fn test_compare_to_item() {
-        let range = Range { start: 5, end: 10 };
+        let range = Range { start: 5, end: 10, count: 1 };
         assert_eq!(range.compare_to_item(0), Ordering::Greater);
         assert_eq!(range.compare_to_item(4), Ordering::Greater);
         assert_eq!(range.compare_to_item(5), Ordering::Equal);
@@ -117,4 +230,84 @@ mod test {
         assert_eq!(range.compare_to_item(11), Ordering::Less);
         assert_eq!(range.compare_to_item(200), Ordering::Less);
     }
+
+    #[test]
+    fn test_from_nested() {
+        // A function called twice, with an if block that never ran and a nested function
+        // that ran once
+        let range_stack = RangeStack::from_nested(vec![
+            Range::from_offsets(0, 100, 1).unwrap(),
+            Range::from_offsets(10, 60, 2).unwrap(),
+            Range::from_offsets(20, 30, 0).unwrap(),
+            Range::from_offsets(40, 50, 1).unwrap(),
+            Range::from_offsets(70, 80, 0).unwrap(),
+        ]);
+
+        assert_eq!(
+            range_stack.ranges,
+            vec![
+                Range { start: 0, end: 9, count: 1 },
+                Range { start: 10, end: 19, count: 2 },
+                Range { start: 30, end: 39, count: 2 },
+                Range { start: 40, end: 49, count: 1 },
+                Range { start: 50, end: 59, count: 2 },
+                Range { start: 60, end: 69, count: 1 },
+                Range { start: 80, end: 99, count: 1 },
+            ]
+        );
+
+        assert_eq!(range_stack.count_at(15), 2);
+        assert_eq!(range_stack.count_at(25), 0);
+        assert_eq!(range_stack.count_at(45), 1);
+        assert_eq!(range_stack.count_at(99), 1);
+        assert_eq!(range_stack.count_at(100), 0);
+        assert!(!range_stack.contains_value(75));
+    }
+
+    #[test]
+    fn test_sum() {
+        let first_run: RangeStack = vec![
+            Range { start: 0, end: 9, count: 1 },
+            Range { start: 20, end: 29, count: 1 },
+        ].into_iter().collect();
+        let second_run: RangeStack = vec![
+            Range { start: 5, end: 24, count: 1 },
+            Range { start: 25, end: 29, count: 4 },
+        ].into_iter().collect();
+
+        assert_eq!(
+            first_run.sum(&second_run).ranges,
+            vec![
+                Range { start: 0, end: 4, count: 1 },
+                Range { start: 5, end: 9, count: 2 },
+                Range { start: 10, end: 19, count: 1 },
+                Range { start: 20, end: 24, count: 2 },
+                Range { start: 25, end: 29, count: 5 },
+            ]
+        );
+        assert_eq!(first_run.sum(&RangeStack { ranges: vec![] }), first_run);
+    }
+
+    #[test]
+    fn test_from_offsets_skips_empty_ranges() {
+        assert_eq!(Range::from_offsets(5, 5, 1), None);
+        assert_eq!(Range::from_offsets(5, 6, 3), Some(Range { start: 5, end: 5, count: 3 }));
+    }
+
+    #[test]
+    fn test_count_range() {
+        let range_stack = RangeStack::from_nested(vec![
+            Range { start: 0, end: 9, count: 2 },
+            Range { start: 4, end: 5, count: 0 },
+            Range { start: 20, end: 29, count: 1 },
+        ]);
+
+        assert_eq!(range_stack.count_range(0, 4), (2, 2));
+        assert_eq!(range_stack.count_range(2, 8), (0, 2));
+        assert_eq!(range_stack.count_range(8, 22), (0, 2));
+        assert_eq!(range_stack.count_range(20, 30), (1, 1));
+        assert_eq!(range_stack.count_range(25, 35), (0, 1));
+        assert_eq!(range_stack.count_range(12, 15), (0, 0));
+        assert_eq!(range_stack.count_range(21, 21), (1, 1));
+    }
 }
@@ -18,6 +18,39 @@ pub struct Settings {
     pub sources: Sources,
 
     pub reify_against_lcov: Option<String>,
+
+    /// Whether to report the source files that never loaded as uncovered
+    #[serde(default)]
+    pub all_files: bool,
+
+    /// Whether to report the sources of the bundles under `dist_path` that never loaded as uncovered
+    #[serde(default)]
+    pub scan_dist: bool,
+
+    /// What to do with sources that changed on disk since the source map was built
+    #[serde(default)]
+    pub on_conflict: ConflictResolution,
+
+    /// Where to write the list of sources that changed since the source map was built
+    #[serde(default)]
+    pub conflict_report: Option<String>,
+
+    /// Where to write the `sourcesContent` of the sources that aren't on disk, to report them anyway
+    #[serde(default)]
+    pub materialize_sources: Option<String>,
+}
+
+/// How to report a source whose file on disk doesn't match the map's `sourcesContent`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictResolution {
+    /// Leave the source out of the report
+    Skip,
+    /// Report the lines of the `sourcesContent`, as if it was on disk
+    #[default]
+    Trust,
+    /// Move the lines of the `sourcesContent` to where they are on disk, dropping changed lines
+    Remap,
 }
 
 #[derive(Debug, Deserialize)]
@@ -1,8 +1,9 @@
 use crate::util::fast_read;
 use crate::model::SourceMap;
+use crate::offsets::split_lines;
 use crate::settings::Settings;
-use std::path::Path;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
 use crate::util;
 use crate::settings::Sources;
 use globset::Candidate;
@@ -16,7 +17,8 @@ pub enum SourceType {
 
 #[derive(Debug, Clone)]
 pub enum FilePath {
-//    Conflict(PathBuf),
+    /// A file on disk that doesn't match the map's `sourcesContent`
+    Conflict(PathBuf),
     Resolved(PathBuf),
     Unresolved(String),
 }
@@ -35,20 +37,36 @@ pub struct NoContentSourceMapSource {
 }
 
 impl Sources {
-    fn is_source_file(&self, path: &str) -> bool {
+    pub fn is_source_file(&self, path: &str) -> bool {
         let chopped = path.replace(&self.base, "");
         let candidate = Candidate::new(&chopped);
         self.dirs.is_match_candidate(&candidate) && !self.excludes.is_match_candidate(&candidate)
     }
 }
 
+/// Where a source of the map lives on disk. Urls without a scheme are resolved against the map's
+/// own location, and when that doesn't point at a file `dist_coverage_url` is swapped for
+/// `dist_coverage_path` instead.
+pub fn resolve_source(settings: &Settings, source_map: &SourceMap, source_url: &str) -> String {
+    if let Some(path) = util::file_url_to_path(source_url) {
+        return path;
+    }
+    if !source_url.contains("://") {
+        let path = source_map.dir.join(util::percent_decode(source_url));
+        if path.exists() {
+            return path.to_string_lossy().to_string();
+        }
+    }
+    source_url.replace(&settings.dist_coverage_url, &settings.dist_coverage_path)
+}
+
 pub fn process_references(settings: &Settings, source_map: &SourceMap) -> Vec<SourceMapSource> {
     source_map
-        .sources
+        .source_urls()
         .iter()
         .enumerate()
         .map(|(ref i, ref source_url)| {
-            let mut abs_source_path = source_url.replace(&settings.dist_coverage_url, &settings.dist_coverage_path);
+            let mut abs_source_path = resolve_source(settings, source_map, source_url);
             if abs_source_path.contains(".pre-build-optimizer.js") {
                 let rewritten = abs_source_path.replace(".pre-build-optimizer.js", "").to_owned();
                 let content = util::fast_read(&rewritten).ok();
@@ -61,18 +79,10 @@ pub fn process_references(settings: &Settings, source_map: &SourceMap) -> Vec<So
 
 
             let path = Path::new(&abs_source_path).canonicalize();
-            let file_path = match path {
+            let mut file_path = match path {
                 Ok(resolved) => {
                     abs_source_path = resolved.to_string_lossy().to_string();
-//
-//                    let content = util::fast_read(&abs_source_path).ok();
-//                    let content_equal = source_map.sourcesContent.as_ref()
-//                        .and_then(|contents| contents.get(*i).map(|inner_content| inner_content == &content)).unwrap_or(false);
-//                    if content_equal {
-                        FilePath::Resolved(resolved)
-//                    } else {
-//                        FilePath::Conflict(resolved)
-//                    }
+                    FilePath::Resolved(resolved)
                 },
                 _ => FilePath::Unresolved(abs_source_path.clone()),
             };
@@ -86,20 +96,70 @@ pub fn process_references(settings: &Settings, source_map: &SourceMap) -> Vec<So
                 SourceType::Generated
             };
 
+            let content = source_map
+                .sourcesContent
+                .as_ref()
+                .and_then(|contents| contents.get(*i).cloned().flatten());
+
+            // Only user sources are compared, the rest never makes it into the report
+            if let (FilePath::Resolved(resolved), SourceType::User, Some(content)) = (&file_path, &source_type, &content) {
+                if util::fast_read(resolved).is_ok_and(|on_disk| !same_text(content, &on_disk)) {
+                    eprintln!("The source file {} changed since its source map was built", &abs_source_path);
+                    file_path = FilePath::Conflict(resolved.clone());
+                }
+            } else if let (FilePath::Unresolved(unresolved), SourceType::User, Some(content)) = (&file_path, &source_type, &content) {
+                if let Some(materialized) = materialize(settings, unresolved, content) {
+                    file_path = FilePath::Resolved(materialized);
+                }
+            }
+
             SourceMapSource {
                 file_path,
                 source_type,
-                content: source_map
-                    .sourcesContent
-                    .as_ref()
-                    .and_then(|contents| contents[*i].clone()),
+                content,
             }
         }).collect()
 }
 
+/// Writes a source that isn't on disk into the `materialize_sources` tree, at its path relative to
+/// `sources.base`, and returns where it went
+fn materialize(settings: &Settings, source_path: &str, content: &str) -> Option<PathBuf> {
+    let output = settings.materialize_sources.as_ref()?;
+    // Only plain components, so the source can't end up outside of the tree
+    let relative = Path::new(util::relative_to(source_path, &settings.sources.base))
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect::<PathBuf>();
+    let path = Path::new(output).join(relative);
+
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, content))
+        .and_then(|_| path.canonicalize());
+    match written {
+        Ok(materialized) => {
+            eprintln!("Materialized source file: {}", materialized.to_string_lossy());
+            Some(materialized)
+        }
+        Err(err) => {
+            eprintln!("Cannot write the source file {} - {}", path.to_string_lossy(), err);
+            None
+        }
+    }
+}
+
+/// Whether two versions of a source have the same text, regardless of line endings
+fn same_text(first: &str, second: &str) -> bool {
+    split_lines(first) == split_lines(second)
+}
+
 impl SourceMapSource {
     pub fn convert_to_user_source(self) -> Option<String> {
-        if let FilePath::Resolved(ref path_buf) = self.file_path {
+        if let FilePath::Resolved(ref path_buf) | FilePath::Conflict(ref path_buf) = self.file_path {
             if let SourceType::User = self.source_type {
                 if path_buf.to_string_lossy().ends_with(".js")
                     || path_buf.to_string_lossy().ends_with(".ts")
@@ -127,8 +187,13 @@ impl SourceMapSource {
 #[cfg(test)]
 mod test {
 
+    use crate::model::SourceMap;
     use crate::settings::Sources;
+    use crate::source_map::{process_references, resolve_source, FilePath};
+    use crate::test_util;
     use globset::{Glob, GlobSetBuilder};
+    use std::fs;
+    use std::path::Path;
 
     #[test]
     fn test_is_source() {
@@ -141,4 +206,44 @@ mod test {
         assert_eq!(true, sources.is_source_file("/test/base/src/some_file"));
         assert_eq!(true, sources.is_source_file("/test/base/src/nested/some_file"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_resolve_source() {
+        let settings = test_util::settings("/project/", "/dist/");
+        let mut source_map: SourceMap = ::serde_json::from_str(
+            r#"{"version":3,"sourceRoot":"src","sources":["lib.rs","webpack:///src/a.js"],"mappings":""}"#,
+        ).unwrap();
+        source_map.dir = Path::new(env!("CARGO_MANIFEST_DIR")).to_path_buf();
+
+        let urls = source_map.source_urls();
+        assert_eq!(
+            resolve_source(&settings, &source_map, &urls[0]),
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lib.rs").to_string_lossy()
+        );
+        assert_eq!(resolve_source(&settings, &source_map, &urls[1]), "/project/src/a.js");
+        assert_eq!(resolve_source(&settings, &source_map, "file:///project/src/b.js"), "/project/src/b.js");
+    }
+
+    #[test]
+    fn test_materialize_missing_sources() {
+        let output = test_util::temp_dir("materialized");
+        let mut settings = test_util::settings("/missing-project/", "/dist/");
+        settings.materialize_sources = Some(output.to_string_lossy().to_string());
+        let source_map: SourceMap = ::serde_json::from_str(
+            r#"{"version":3,"sources":["webpack:///src/app/a.js","webpack:///lib/b.js"],"sourcesContent":["f();\n","g();\n"],"mappings":""}"#,
+        ).unwrap();
+
+        let references = process_references(&settings, &source_map);
+        let materialized = fs::read_to_string(output.join("src/app/a.js"));
+        let skipped = output.join("lib/b.js").exists();
+        fs::remove_dir_all(&output).unwrap();
+
+        match references[0].file_path {
+            FilePath::Resolved(ref path) => assert!(path.ends_with("src/app/a.js")),
+            ref other => panic!("Expected a materialized source, got {:?}", other),
+        }
+        assert_eq!(materialized.unwrap(), "f();\n");
+        assert!(matches!(references[1].file_path, FilePath::Unresolved(_)));
+        assert!(!skipped);
+    }
+}
@@ -0,0 +1,660 @@
+use crate::offsets::is_line_break;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    Word,
+    Punct,
+    Literal,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// The 0 based line the token starts on
+    pub line: usize,
+    /// The UTF-16 column the token starts at, like source map columns
+    pub column: usize,
+    pub newline_before: bool,
+}
+
+impl<'a> Token<'a> {
+    fn is(&self, kind: TokenKind, text: &str) -> bool {
+        self.kind == kind && self.text == text
+    }
+
+    pub fn is_punct(&self, text: &str) -> bool {
+        self.is(TokenKind::Punct, text)
+    }
+
+    pub fn is_word(&self, text: &str) -> bool {
+        self.is(TokenKind::Word, text)
+    }
+}
+
+/// Keywords after which an expression has to follow, so they can't end a statement
+const NON_ENDING_WORDS: &[&str] = &[
+    "typeof", "new", "delete", "void", "in", "instanceof", "of", "else", "do", "case", "extends",
+    "var", "let", "const", "await", "yield", "export", "import", "async",
+];
+
+/// Keywords after which a `/` starts a regular expression rather than being a division
+const REGEX_AFTER_WORDS: &[&str] = &[
+    "return", "typeof", "case", "do", "else", "in", "of", "instanceof", "new", "delete", "void",
+    "throw", "yield", "await",
+];
+
+/// Keywords followed by a parenthesized header, after which the statement's body starts
+pub const CONTROL_WORDS: &[&str] = &["if", "for", "while", "with", "switch", "catch"];
+
+/// Punctuators longer than a character, longest first
+const PUNCTUATORS: &[&str] = &[
+    ">>>=", "...", "===", "!==", "**=", "<<=", ">>=", "&&=", "||=", "??=", "=>", "==", "!=", "<=",
+    ">=", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "&&", "||", "??", "++", "--", "?.",
+];
+
+struct Tokenizer<'a> {
+    text: &'a str,
+    position: usize,
+    line: usize,
+    line_start: usize,
+    /// Where the line the current token starts on begins
+    token_line_start: usize,
+    newline_before: bool,
+    /// Whether each open brace is a template literal's `${` substitution
+    braces: Vec<bool>,
+    regex_allowed: bool,
+    tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.text[self.position..].chars().nth(1)
+    }
+
+    /// Moves past the next character, keeping track of the line
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        if is_line_break(c, self.peek()) {
+            self.line += 1;
+            self.line_start = self.position;
+            self.newline_before = true;
+        }
+        Some(c)
+    }
+
+    fn push(&mut self, kind: TokenKind, start: usize, line: usize, newline_before: bool) {
+        let text = &self.text[start..self.position];
+        let column = self.text[self.token_line_start..start].encode_utf16().count();
+        self.regex_allowed = match kind {
+            TokenKind::Word => REGEX_AFTER_WORDS.contains(&text),
+            TokenKind::Literal => text.ends_with("${"),
+            TokenKind::Punct => !matches!(text, ")" | "]" | "}" | "++" | "--"),
+        };
+        self.tokens.push(Token { kind, text, line, column, newline_before });
+        self.newline_before = false;
+    }
+
+    /// Skips to the end of the line, leaving the line break itself
+    fn skip_line(&mut self) {
+        while let Some(c) = self.peek() {
+            if is_line_break(c, self.peek_second()) || c == '\r' {
+                break;
+            }
+            self.bump();
+        }
+    }
+
+    /// Quoted strings and regular expressions, which can't span lines
+    fn skip_quoted(&mut self, quote: char) {
+        let mut in_class = false;
+        while let Some(c) = self.peek() {
+            if is_line_break(c, self.peek_second()) || c == '\r' {
+                return;
+            }
+            self.bump();
+            match c {
+                '\\' => {
+                    // Escapes, including line continuations in strings
+                    if self.peek() == Some('\r') && self.peek_second() == Some('\n') {
+                        self.bump();
+                    }
+                    self.bump();
+                }
+                '[' if quote == '/' => in_class = true,
+                ']' if quote == '/' => in_class = false,
+                _ if c == quote && !in_class => return,
+                _ => {}
+            }
+        }
+    }
+
+    /// The rest of a template literal, up to its end or its next `${`
+    fn template(&mut self, start: usize, line: usize, newline_before: bool) {
+        while let Some(c) = self.bump() {
+            match c {
+                '\\' => {
+                    self.bump();
+                }
+                '`' => break,
+                '$' if self.peek() == Some('{') => {
+                    self.bump();
+                    self.braces.push(true);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        self.push(TokenKind::Literal, start, line, newline_before);
+    }
+
+    fn tokenize(mut self) -> Vec<Token<'a>> {
+        // A hashbang line isn't javascript
+        if self.text.starts_with("#!") {
+            self.skip_line();
+        }
+
+        while let Some(c) = self.peek() {
+            let (start, line, newline_before) = (self.position, self.line, self.newline_before);
+            self.token_line_start = self.line_start;
+            let rest = &self.text[start..];
+
+            if c.is_whitespace() {
+                self.bump();
+            } else if rest.starts_with("//") {
+                self.skip_line();
+            } else if rest.starts_with("/*") {
+                self.bump();
+                self.bump();
+                while !self.text[self.position..].starts_with("*/") && self.bump().is_some() {}
+                self.bump();
+                self.bump();
+            } else if c == '\'' || c == '"' {
+                self.bump();
+                self.skip_quoted(c);
+                self.push(TokenKind::Literal, start, line, newline_before);
+            } else if c == '/' && self.regex_allowed {
+                self.bump();
+                self.skip_quoted('/');
+                while self.peek().is_some_and(|c| c.is_alphanumeric()) {
+                    self.bump();
+                }
+                self.push(TokenKind::Literal, start, line, newline_before);
+            } else if c == '`' {
+                self.bump();
+                self.template(start, line, newline_before);
+            } else if c.is_ascii_digit() || (c == '.' && self.peek_second().is_some_and(|c| c.is_ascii_digit())) {
+                while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '.' || c == '_') {
+                    self.bump();
+                }
+                self.push(TokenKind::Literal, start, line, newline_before);
+            } else if c.is_alphabetic() || c == '_' || c == '$' || c == '\\' || c == '#' || !c.is_ascii() {
+                self.bump();
+                while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '$' || c == '\\' || !c.is_ascii()) {
+                    self.bump();
+                }
+                self.push(TokenKind::Word, start, line, newline_before);
+            } else if c == '}' && self.braces.last() == Some(&true) {
+                // The end of a template substitution, the template carries on after it
+                self.braces.pop();
+                self.bump();
+                self.template(start, line, newline_before);
+            } else {
+                match PUNCTUATORS.iter().find(|punctuator| rest.starts_with(**punctuator)) {
+                    Some(punctuator) => self.position += punctuator.len(),
+                    None => {
+                        self.bump();
+                    }
+                }
+                match c {
+                    '{' => self.braces.push(false),
+                    '}' => {
+                        self.braces.pop();
+                    }
+                    _ => {}
+                }
+                self.push(TokenKind::Punct, start, line, newline_before);
+            }
+        }
+        self.tokens
+    }
+}
+
+pub fn tokenize(text: &str) -> Vec<Token<'_>> {
+    Tokenizer {
+        text,
+        position: 0,
+        line: 0,
+        line_start: 0,
+        token_line_start: 0,
+        newline_before: false,
+        braces: vec![],
+        regex_allowed: true,
+        tokens: vec![],
+    }
+    .tokenize()
+}
+
+#[derive(Debug)]
+enum Context {
+    /// A list of statements: the top level, a block, or a function body
+    Block {
+        expect_statement: bool,
+        /// Whether the block is a statement itself (rather than e.g. a function body)
+        statement: bool,
+        /// Whether a function or class declaration is waiting for its body to close
+        declaration: bool,
+        /// Whether the current `case ...:` label hasn't been closed yet
+        in_case: bool,
+    },
+    Class,
+    Paren {
+        control: bool,
+    },
+    /// Brackets, object literals, and template substitutions
+    Other,
+}
+
+impl Context {
+    fn block(statement: bool) -> Context {
+        Context::Block { expect_statement: true, statement, declaration: false, in_case: false }
+    }
+}
+
+pub fn can_end_statement(token: &Token<'_>) -> bool {
+    match token.kind {
+        TokenKind::Word => !NON_ENDING_WORDS.contains(&token.text),
+        TokenKind::Literal => true,
+        TokenKind::Punct => matches!(token.text, ")" | "]" | "}" | "++" | "--"),
+    }
+}
+
+pub fn can_start_statement(token: &Token<'_>) -> bool {
+    match token.kind {
+        TokenKind::Word => !matches!(token.text, "in" | "instanceof" | "of"),
+        TokenKind::Literal => !token.text.starts_with('`'),
+        TokenKind::Punct => matches!(token.text, "{" | "++" | "--" | "!" | "~"),
+    }
+}
+
+/// Whether a statement starting with `tokens[idx]` is one istanbul counts. Declarations of
+/// functions and classes, imports and re-exports aren't statements to it, and neither are
+/// typescript's type only declarations.
+fn counts_as_statement(tokens: &[Token<'_>], idx: usize) -> bool {
+    let token = &tokens[idx];
+    let next = tokens.get(idx + 1);
+    if token.kind != TokenKind::Word {
+        return !token.is_punct("{") && !token.is_punct(";");
+    }
+    let next_is_word = next.is_some_and(|next| next.kind == TokenKind::Word && !next.newline_before);
+    match token.text {
+        "function" | "class" => false,
+        "async" => !next.is_some_and(|next| next.is_word("function")),
+        "abstract" => !next.is_some_and(|next| next.is_word("class")),
+        "interface" | "declare" => !next_is_word,
+        "type" => !(next_is_word && tokens.get(idx + 2).is_some_and(|after| after.is_punct("=") || after.is_punct("<"))),
+        // A const enum is inlined wherever it's used
+        "const" => !next.is_some_and(|next| next.is_word("enum")),
+        "import" => next.is_some_and(|next| next.is_punct("(") || next.is_punct(".")),
+        "export" => match next {
+            Some(next) if next.is_punct("{") || next.is_punct("*") => false,
+            Some(next) if next.is_word("default") => counts_as_statement(tokens, idx + 1),
+            Some(next) if next.kind == TokenKind::Word => counts_as_statement(tokens, idx + 1),
+            _ => true,
+        },
+        "default" => !tokens.get(idx + 1).is_some_and(|next| next.is_word("function") || next.is_word("class")),
+        _ => true,
+    }
+}
+
+const DECLARATION_MODIFIERS: &[&str] = &["export", "default", "async", "abstract", "declare"];
+
+/// The index of the first token after the declaration modifiers at `idx`, if there is one
+fn after_modifiers(tokens: &[Token<'_>], idx: usize) -> Option<usize> {
+    let mut idx = idx;
+    while DECLARATION_MODIFIERS.iter().any(|modifier| tokens[idx].is_word(modifier)) {
+        tokens.get(idx + 1)?;
+        idx += 1;
+    }
+    Some(idx)
+}
+
+fn is_declaration(tokens: &[Token<'_>], idx: usize) -> bool {
+    after_modifiers(tokens, idx).is_some_and(|idx| {
+        tokens[idx].is_word("function") || tokens[idx].is_word("class") || is_namespace(tokens, idx)
+    })
+}
+
+/// A typescript `namespace` (or `module`), whose body holds statements
+fn is_namespace(tokens: &[Token<'_>], idx: usize) -> bool {
+    (tokens[idx].is_word("namespace") || tokens[idx].is_word("module"))
+        && tokens.get(idx + 1).is_some_and(|next| next.kind == TokenKind::Word && !next.newline_before)
+}
+
+/// The index just past a decorator like `@Component({ ... })` starting at `idx`
+fn decorator_end(tokens: &[Token<'_>], idx: usize) -> usize {
+    let mut end = idx + 2;
+    while tokens.get(end).is_some_and(|token| token.is_punct(".")) {
+        end += 2;
+    }
+    if tokens.get(end).is_some_and(|token| token.is_punct("(")) {
+        let mut depth = 0;
+        while let Some(token) = tokens.get(end) {
+            end += 1;
+            match token.text {
+                "(" if token.kind == TokenKind::Punct => depth += 1,
+                ")" if token.kind == TokenKind::Punct => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+    end
+}
+
+/// Whether a `{` after `previous` in a typescript type annotation starts an object type, rather
+/// than following a complete type
+fn starts_object_type(previous: Option<&Token<'_>>) -> bool {
+    previous.is_none_or(|previous| {
+        previous.kind == TokenKind::Punct && matches!(previous.text, ":" | "|" | "&" | "," | "(" | "<" | "=>" | "?")
+    })
+}
+
+/// The 0 based lines that a statement starts on, which are the lines istanbul reports as
+/// executable. Lines that only continue a statement, like the properties of an object literal
+/// or the rest of a multi-line expression, aren't included.
+///
+/// Typescript syntax is understood too: type only declarations, decorators and type
+/// annotations don't start statements, and none of them are valid javascript otherwise.
+pub fn statement_lines(source: &str) -> HashSet<usize> {
+    let tokens = tokenize(source);
+    let mut lines = HashSet::new();
+    let mut contexts = vec![Context::block(false)];
+    let mut control_paren = false;
+    let mut pending_class = false;
+    let mut pending_namespace = false;
+    // The context depth of a return type annotation, e.g. `(): { a: number } {`
+    let mut return_type: Option<usize> = None;
+    let mut skip_to = 0;
+
+    for (idx, token) in tokens.iter().enumerate() {
+        let previous = idx.checked_sub(1).map(|idx| &tokens[idx]);
+        let next = tokens.get(idx + 1);
+        let after_dot = previous.is_some_and(|previous| previous.is_punct(".") || previous.is_punct("?."));
+
+        if idx < skip_to {
+            continue;
+        }
+        if token.is_punct("@") && next.is_some_and(|next| next.kind == TokenKind::Word) {
+            // Decorators and their arguments are metadata, not statements
+            skip_to = decorator_end(&tokens, idx);
+            continue;
+        }
+
+        if let Some(depth) = return_type {
+            let ends_type = contexts.len() == depth
+                && token.kind == TokenKind::Punct
+                && matches!(token.text, ";" | "," | "=" | "=>");
+            if contexts.len() < depth || ends_type {
+                return_type = None;
+            }
+        }
+
+        if let Some(Context::Block { expect_statement, in_case, declaration, .. }) = contexts.last_mut() {
+            // Automatic semicolon insertion, a new line that can't continue the statement
+            let asi = !*expect_statement
+                && !*in_case
+                && token.newline_before
+                && previous.is_some_and(can_end_statement)
+                && can_start_statement(token);
+            if asi {
+                *expect_statement = true;
+                *declaration = false;
+            }
+
+            if *expect_statement {
+                let is_case = token.is_word("case") || (token.is_word("default") && next.is_some_and(|next| next.is_punct(":")));
+                if is_case {
+                    *in_case = true;
+                    *expect_statement = false;
+                } else if token.kind == TokenKind::Word && next.is_some_and(|next| next.is_punct(":")) {
+                    // A label, along with its `:`
+                    skip_to = idx + 2;
+                    continue;
+                } else if token.is_word("else") || token.is_word("finally") || token.is_word("do") {
+                    // These keep expecting the statement (or block) that follows
+                    if token.is_word("do") {
+                        lines.insert(token.line);
+                    }
+                    continue;
+                } else if token.is_word("catch") {
+                    *expect_statement = false;
+                } else if !token.is_punct("{") && !token.is_punct("}") && !token.is_punct(";") {
+                    if counts_as_statement(&tokens, idx) {
+                        lines.insert(token.line);
+                    }
+                    *declaration = is_declaration(&tokens, idx);
+                    // An ambient `declare namespace` only holds declarations
+                    pending_namespace = after_modifiers(&tokens, idx).is_some_and(|start| {
+                        is_namespace(&tokens, start) && !tokens[idx..start].iter().any(|token| token.is_word("declare"))
+                    });
+                    *expect_statement = false;
+                }
+            }
+        }
+
+        if token.kind == TokenKind::Word && !after_dot {
+            if CONTROL_WORDS.contains(&token.text) {
+                control_paren = true;
+            } else if token.text == "class" {
+                pending_class = true;
+            }
+        }
+
+        if token.kind == TokenKind::Literal {
+            // Template literals are split up around their `${...}` substitutions
+            if token.text.starts_with('}') && contexts.len() > 1 {
+                contexts.pop();
+            }
+            if (token.text.starts_with('`') || token.text.starts_with('}')) && token.text.ends_with("${") {
+                contexts.push(Context::Other);
+            }
+        }
+
+        match token.text {
+            _ if token.kind != TokenKind::Punct
+                && !token.is_word("await")
+                && !CONTROL_WORDS.contains(&token.text) =>
+            {
+                control_paren = false;
+            }
+            "(" => {
+                contexts.push(Context::Paren { control: control_paren });
+                control_paren = false;
+            }
+            "[" => contexts.push(Context::Other),
+            "{" => {
+                let after_keyword = previous.is_some_and(|previous| {
+                    ["else", "try", "catch", "finally", "do"].iter().any(|word| previous.is_word(word))
+                });
+                let opens_block = after_keyword
+                    || previous.is_none_or(|previous| previous.is_punct(")") || previous.is_punct("=>"));
+                let in_return_type = return_type == Some(contexts.len());
+                let context = match contexts.last_mut() {
+                    Some(Context::Block { expect_statement, .. }) if *expect_statement || after_keyword => {
+                        *expect_statement = false;
+                        Context::block(true)
+                    }
+                    _ if in_return_type && starts_object_type(previous) => Context::Other,
+                    _ if in_return_type => {
+                        return_type = None;
+                        Context::block(false)
+                    }
+                    _ if pending_class => Context::Class,
+                    _ if pending_namespace => Context::block(false),
+                    _ if opens_block => Context::block(false),
+                    _ => Context::Other,
+                };
+                pending_class = false;
+                pending_namespace = false;
+                contexts.push(context);
+            }
+            ")" | "]" | "}" => {
+                let closed = if contexts.len() > 1 { contexts.pop() } else { None };
+                if let Some(Context::Block { expect_statement, declaration, .. }) = contexts.last_mut() {
+                    match closed {
+                        Some(Context::Paren { control: true }) => *expect_statement = true,
+                        Some(Context::Block { statement: true, .. }) => *expect_statement = true,
+                        Some(Context::Block { .. }) | Some(Context::Class) if *declaration => {
+                            *expect_statement = true;
+                            *declaration = false;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            ";" => {
+                if let Some(Context::Block { expect_statement, declaration, .. }) = contexts.last_mut() {
+                    *expect_statement = true;
+                    *declaration = false;
+                }
+            }
+            ":" => match contexts.last_mut() {
+                Some(Context::Block { expect_statement, in_case, .. }) if *in_case => {
+                    *in_case = false;
+                    *expect_statement = true;
+                }
+                _ if previous.is_some_and(|previous| previous.is_punct(")")) => return_type = Some(contexts.len()),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use crate::statements::statement_lines;
+
+    fn sorted_lines(source: &str) -> Vec<usize> {
+        let mut lines = statement_lines(source).into_iter().collect::<Vec<_>>();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn test_object_literals_and_multi_line_expressions() {
+        let source = "const config = {\n  a: 1,\n  b: [\n    2,\n  ],\n};\nconst sum = 1 +\n  2 +\n  3;\nfoo(\n  sum\n);\n";
+        assert_eq!(sorted_lines(source), vec![0, 6, 9]);
+    }
+
+    #[test]
+    fn test_functions_and_control_flow() {
+        let source = r#"import { a } from "./a";
+
+export function sub(a, b) {
+  if (a > b) {
+    return a - b;
+  } else if (b > a)
+    return b - a;
+  for (let i = 0; i < 3; i++) log(i);
+  return 0;
+}
+
+const f = (x) => {
+  switch (x) {
+    case 1:
+      return "one";
+    default:
+      return `${x} + ${
+        x
+      }`;
+  }
+};
+"#;
+        assert_eq!(sorted_lines(source), vec![3, 4, 5, 6, 7, 8, 11, 12, 14, 16]);
+    }
+
+    #[test]
+    fn test_catch_without_binding() {
+        let source = "try {\n  a();\n} catch {\n  b();\n} finally {\n  c();\n}\n";
+        assert_eq!(sorted_lines(source), vec![0, 1, 3, 5]);
+        let source = "try {\n  a();\n} catch (e) {\n  b();\n}\n";
+        assert_eq!(sorted_lines(source), vec![0, 1, 3]);
+    }
+
+    #[test]
+    fn test_semicolonless_statements() {
+        let source = "let a = 1\nlet b = a\n  .toString()\nfoo()\n/* comment */ bar()\nwhile (a) a--\n";
+        assert_eq!(sorted_lines(source), vec![0, 1, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_classes_and_tricky_tokens() {
+        let source = "class A extends B {\n  method() {\n    const r = /}{/g;\n    return 'a}{' + \"b\";\n  }\n}\nafter();\n";
+        assert_eq!(sorted_lines(source), vec![2, 3, 6]);
+    }
+
+    #[test]
+    fn test_typescript_declarations() {
+        let source = r#"interface Point {
+  x: number;
+  y: number;
+}
+type Pair = {
+  first: Point;
+};
+declare module "lib" {
+  const value: number;
+}
+export function scale(p: Point): Point;
+export function scale(p: Point, by = 2): { x: number } {
+  return { x: p.x * by };
+}
+namespace Shapes {
+  export const origin = 0;
+}
+const enum Direction { Up }
+enum Color { Red }
+export namespace NT {
+  g();
+}
+export module M {
+  h();
+}
+export declare namespace D {
+  const d: number;
+}
+"#;
+        assert_eq!(sorted_lines(source), vec![12, 14, 15, 18, 19, 20, 22, 23]);
+    }
+
+    #[test]
+    fn test_decorators() {
+        let source = r#"@Component({
+  selector: "app-root",
+  template: "<div></div>",
+})
+export class AppComponent {
+  @Input() name: string;
+
+  constructor(@Inject(TOKEN) private token: string) {}
+
+  greet(): Promise<void> {
+    return this.load();
+  }
+}
+"#;
+        assert_eq!(sorted_lines(source), vec![10]);
+    }
+}
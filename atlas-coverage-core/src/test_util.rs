@@ -0,0 +1,21 @@
+//! Fixtures shared by the tests of the other modules
+
+use crate::settings::Settings;
+use std::path::PathBuf;
+
+/// Settings for a project at `base`, with its sources under `src/` and its bundles in `dist_path`
+pub fn settings(base: &str, dist_path: &str) -> Settings {
+    ::serde_json::from_value(::serde_json::json!({
+        "public_url_base": "http://localhost/",
+        "dist_path": dist_path,
+        "dist_coverage_url": "webpack:///",
+        "dist_coverage_path": base,
+        "sources": { "base": base, "dirs": ["src/**"], "excludes": [] },
+        "reify_against_lcov": null
+    })).unwrap()
+}
+
+/// A directory in the system temp dir that no other test, or test run, uses
+pub fn temp_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("atlas-coverage-{}-{}", name, std::process::id()))
+}
@@ -0,0 +1,235 @@
+use crate::branches::branch_points;
+use crate::lines::{add_branch_lines, BranchCoverage, FileCoverage, LineCoverage};
+use crate::model::{find_source_mapping_url, PuppeteerData};
+use crate::range::RangeStack;
+use crate::settings::{Settings, Sources};
+use crate::statements::statement_lines;
+use crate::util;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directories that never hold user sources, and can be huge
+const SKIPPED_DIRECTORIES: &[&str] = &["node_modules", ".git"];
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension().and_then(|extension| extension.to_str()).is_some_and(|extension| extensions.contains(&extension))
+}
+
+fn walk(directory: &Path, extensions: &[&str], files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("Cannot read the directory {} - {}", directory.to_string_lossy(), err);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let skipped = entry.file_name().to_str().is_some_and(|name| SKIPPED_DIRECTORIES.contains(&name));
+        if path.is_dir() && !skipped {
+            walk(&path, extensions, files);
+        } else if path.is_file() && has_extension(&path, extensions) {
+            files.push(path);
+        }
+    }
+}
+
+pub fn canonical(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+/// The coverage of a source that never ran: every executable line and branch is uncovered
+fn uncovered_file(path: String, source: String) -> FileCoverage {
+    let mut lines = statement_lines(&source).into_iter().collect::<Vec<_>>();
+    lines.sort_unstable();
+    let branches: Vec<_> = branch_points(&source)
+        .into_iter()
+        .map(|point| BranchCoverage {
+            kind: point.kind,
+            line_number: point.position.line + 1,
+            hits: vec![0; point.arms.len() + point.implicit_else as usize],
+        })
+        .collect();
+    let mut lines = lines.into_iter().map(|line| LineCoverage { line_number: line + 1, hits: 0, partial: false }).collect();
+    add_branch_lines(&mut lines, branches.iter().map(|branch| (branch.line_number, 0)));
+
+    FileCoverage {
+        path,
+        lines,
+        functions: vec![],
+        branches,
+        source: Some(source),
+    }
+}
+
+/// The source files matching the `sources` globs that don't have any coverage, e.g. because
+/// they're in a lazy chunk that was never loaded. The `skipped` paths were loaded but left out
+/// on purpose, so they aren't either.
+pub fn unloaded_files(sources: &Sources, covered: &[FileCoverage], skipped: &[String]) -> Vec<FileCoverage> {
+    let covered: HashSet<_> = covered
+        .iter()
+        .map(|file| &file.path)
+        .chain(skipped)
+        .map(|path| canonical(path))
+        .collect();
+
+    let mut files = vec![];
+    walk(Path::new(&sources.base), &["js", "ts"], &mut files);
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .filter(|path| sources.is_source_file(path) && !covered.contains(&canonical(path)))
+        .filter_map(|path| match util::fast_read(&path) {
+            Ok(source) => {
+                eprintln!("Adding unloaded source file: {}", &path);
+                Some(uncovered_file(path, source))
+            }
+            Err(err) => {
+                eprintln!("Cannot read the source file {} - {}", &path, err);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The bundles under the `dist_path` that have a source map but no coverage, e.g. lazy route
+/// chunks that were never loaded. They come back without any ranges, so everything they map to
+/// is uncovered. `loaded` holds the canonical paths of the bundles that do have coverage.
+pub fn unloaded_bundles(settings: &Settings, loaded: &HashSet<PathBuf>) -> Vec<PuppeteerData> {
+    let mut files = vec![];
+    walk(Path::new(&settings.dist_path), &["js"], &mut files);
+    files.sort();
+
+    files
+        .into_iter()
+        .filter(|path| !loaded.contains(&canonical(&path.to_string_lossy())))
+        .filter_map(|path| {
+            let text = match util::fast_read(&path) {
+                Ok(text) => text,
+                Err(err) => {
+                    eprintln!("Cannot read the bundle {} - {}", path.to_string_lossy(), err);
+                    return None;
+                }
+            };
+            let sibling_map = PathBuf::from(format!("{}.map", path.to_string_lossy()));
+            if find_source_mapping_url(&text).is_none() && !sibling_map.exists() {
+                return None;
+            }
+
+            // The url the bundle would be served from, the reverse of `script_path`
+            let relative = path.strip_prefix(&settings.dist_path).ok()?;
+            let relative = relative.components().map(|part| part.as_os_str().to_string_lossy()).collect::<Vec<_>>();
+            let url = format!("{}{}", settings.public_url_base, relative.join("/"));
+            eprintln!("Adding unloaded bundle: {}", url);
+
+            Some(PuppeteerData {
+                url,
+                text,
+                ranges: RangeStack { ranges: vec![] },
+                source_map_url: None,
+                source_map: None,
+                functions: vec![],
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lines::FileCoverage;
+    use crate::settings::Sources;
+    use crate::test_util;
+    use crate::lines::ManyCoverage;
+    use crate::unloaded::{canonical, uncovered_file, unloaded_bundles, unloaded_files};
+    use globset::{Glob, GlobSetBuilder};
+    use std::collections::HashSet;
+    use std::fs;
+
+    #[test]
+    fn test_unloaded_files() {
+        let base = test_util::temp_dir("unloaded");
+        fs::create_dir_all(base.join("src/lazy")).unwrap();
+        fs::create_dir_all(base.join("src/node_modules/dep")).unwrap();
+        fs::write(base.join("src/loaded.js"), "f();\n").unwrap();
+        fs::write(base.join("src/lazy/chunk.js"), "const a = {\n  b: 1,\n};\nif (a.b) {\n  go();\n}\n").unwrap();
+        fs::write(base.join("src/lazy/chunk.spec.js"), "it();\n").unwrap();
+        fs::write(base.join("src/stale.js"), "g();\n").unwrap();
+        fs::write(base.join("src/lazy/styles.css"), "a {}\n").unwrap();
+        fs::write(base.join("src/node_modules/dep/index.js"), "dep();\n").unwrap();
+
+        let base_path = format!("{}/", base.to_string_lossy());
+        let sources = Sources {
+            base: base_path.clone(),
+            dirs: GlobSetBuilder::new().add(Glob::new("src/**").unwrap()).build().unwrap(),
+            excludes: GlobSetBuilder::new().add(Glob::new("**/*.spec.js").unwrap()).build().unwrap(),
+        };
+        let covered = vec![FileCoverage {
+            path: format!("{}src/loaded.js", base_path),
+            lines: vec![],
+            functions: vec![],
+            branches: vec![],
+            source: None,
+        }];
+
+        let skipped = vec![format!("{}src/stale.js", base_path)];
+        let unloaded = unloaded_files(&sources, &covered, &skipped);
+        fs::remove_dir_all(&base).unwrap();
+
+        assert_eq!(unloaded.len(), 1);
+        assert_eq!(unloaded[0].path, format!("{}src/lazy/chunk.js", base_path));
+        let lines = unloaded[0].lines.iter().map(|line| (line.line_number, line.hits)).collect::<Vec<_>>();
+        assert_eq!(lines, vec![(1, 0), (4, 0), (5, 0)]);
+        assert_eq!(unloaded[0].branches_valid(), 2);
+        assert_eq!(unloaded[0].branches_covered(), 0);
+    }
+
+    #[test]
+    fn test_unloaded_bundles() {
+        let dist = test_util::temp_dir("dist");
+        fs::create_dir_all(dist.join("chunks")).unwrap();
+        fs::write(dist.join("main.js"), "a();\n//# sourceMappingURL=main.js.map\n").unwrap();
+        fs::write(dist.join("chunks/lazy.js"), "b();\n//# sourceMappingURL=lazy.js.map\n").unwrap();
+        fs::write(dist.join("chunks/sibling.js"), "c();\n").unwrap();
+        fs::write(dist.join("chunks/sibling.js.map"), "{}").unwrap();
+        fs::write(dist.join("chunks/unmapped.js"), "d();\n").unwrap();
+
+        let dist_path = format!("{}/", dist.to_string_lossy());
+        let mut settings = test_util::settings("/project/", &dist_path);
+        settings.public_url_base = "http://localhost/js/".to_owned();
+        settings.scan_dist = true;
+        let loaded: HashSet<_> = vec![canonical(&format!("{}main.js", dist_path))].into_iter().collect();
+
+        let bundles = unloaded_bundles(&settings, &loaded);
+        fs::remove_dir_all(&dist).unwrap();
+
+        let urls = bundles.iter().map(|bundle| bundle.url.as_str()).collect::<Vec<_>>();
+        assert_eq!(urls, vec!["http://localhost/js/chunks/lazy.js", "http://localhost/js/chunks/sibling.js"]);
+        assert!(bundles.iter().all(|bundle| bundle.ranges.ranges.is_empty()));
+        assert_eq!(bundles[1].text, "c();\n");
+    }
+
+    #[test]
+    fn test_default_parameter_branches_stay_on_their_line() {
+        let first_line = uncovered_file("/a.js".to_owned(), "function add(a, b = 2) {\n  return a + b;\n}\n".to_owned());
+        let after_statement = uncovered_file(
+            "/b.js".to_owned(),
+            "const config = {\n  a: 1,\n};\nfunction add(a, b = 2) {\n  return a + b;\n}\n".to_owned(),
+        );
+
+        let summary = |file: &FileCoverage| {
+            let lines = file.lines.iter().map(|line| line.line_number).collect::<Vec<_>>();
+            (lines, file.branches_by_line().into_iter().collect::<Vec<_>>())
+        };
+        assert_eq!(summary(&first_line), (vec![1, 2], vec![(1, (0, 1))]));
+        assert_eq!(summary(&after_statement), (vec![1, 4, 5], vec![(4, (0, 1))]));
+
+        let mut xml = vec![];
+        ManyCoverage { files: vec![after_statement] }.write_xml(&mut xml).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains(r#"<lineToCover lineNumber="1" covered="false" />"#));
+        assert!(xml.contains(r#"<lineToCover lineNumber="4" covered="false" branchesToCover="1" coveredBranches="0" />"#));
+    }
+}
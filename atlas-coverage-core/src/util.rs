@@ -1,6 +1,7 @@
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use serde::de::DeserializeOwned;
 use std::fs::OpenOptions;
+use std::io;
 use std::error::Error;
 use std::str;
 
@@ -19,4 +20,132 @@ pub fn fast_read(source_path: impl AsRef<Path>) -> Result<String, Box<dyn Error>
     let file = OpenOptions::new().read(true).open(source_path.as_ref())?;
     let mmap = unsafe { ::memmap::Mmap::map(&file) }?;
     Ok(str::from_utf8(&mmap)?.to_owned())
-}
\ No newline at end of file
+}
+
+/// Turns an error of the xml writer into the io error it wraps, when it wraps one
+pub fn xml_error(err: ::xml::writer::Error) -> io::Error {
+    match err {
+        ::xml::writer::Error::Io(err) => err,
+        err => io::Error::other(err.to_string()),
+    }
+}
+
+/// Resolves the `.` and `..` components of a path, without looking at the file system (the path
+/// may not exist)
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+pub fn relative_to<'a>(path: &'a str, base: &str) -> &'a str {
+    let base = base.trim_end_matches('/');
+    match path.strip_prefix(base) {
+        Some(rest) if !base.is_empty() && (rest.is_empty() || rest.starts_with('/')) => {
+            rest.trim_start_matches('/')
+        }
+        _ => path,
+    }
+}
+
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let hex = bytes
+            .get(idx + 1..idx + 3)
+            .and_then(|hex| str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[idx], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                idx += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                idx += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Converts a `file://` url to a path on disk, or `None` for any other kind of url
+pub fn file_url_to_path(url: &str) -> Option<String> {
+    let path = url.strip_prefix("file://")?;
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    let path = percent_decode(path);
+
+    // file:///C:/some/path is C:/some/path on windows
+    let is_drive_path = path.len() > 2 && path.starts_with('/') && path.as_bytes()[2] == b':';
+    if is_drive_path {
+        Some(path[1..].to_owned())
+    } else {
+        Some(path)
+    }
+}
+
+/// Decodes the content of a `data:` url, either base64 or percent encoded
+pub fn decode_data_url(url: &str) -> Option<Result<String, Box<dyn Error>>> {
+    let (header, data) = url.strip_prefix("data:")?.split_once(',')?;
+    if header.split(';').any(|param| param.trim().eq_ignore_ascii_case("base64")) {
+        let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+        let bytes = ::base64::decode(percent_decode(&data));
+        Some(bytes.map_err(Box::from).and_then(|bytes| Ok(String::from_utf8(bytes)?)))
+    } else {
+        Some(Ok(percent_decode(data)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::{decode_data_url, file_url_to_path, normalize_path, percent_decode};
+    use std::path::Path;
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b%2Fc"), "a b/c");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%e2%9c%93"), "\u{2713}");
+    }
+
+    #[test]
+    fn test_file_url_to_path() {
+        assert_eq!(file_url_to_path("file:///srv/app/server%20side.js"), Some("/srv/app/server side.js".to_owned()));
+        assert_eq!(file_url_to_path("file://localhost/srv/app.js"), Some("/srv/app.js".to_owned()));
+        assert_eq!(file_url_to_path("file:///C:/app/main.js"), Some("C:/app/main.js".to_owned()));
+        assert_eq!(file_url_to_path("http://localhost/app.js"), None);
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(normalize_path(Path::new("/project/build/../src/./app.ts")), Path::new("/project/src/app.ts"));
+        assert_eq!(normalize_path(Path::new("/../app.ts")), Path::new("/app.ts"));
+        assert_eq!(normalize_path(Path::new("../../src/a.ts")), Path::new("../../src/a.ts"));
+    }
+
+    #[test]
+    fn test_decode_data_url() {
+        let decoded = decode_data_url("data:application/json;charset=utf-8;base64,eyJ2ZXJzaW9uIjozfQ==");
+        assert_eq!(decoded.unwrap().unwrap(), r#"{"version":3}"#);
+
+        let decoded = decode_data_url("data:application/json,%7B%22version%22%3A3%7D");
+        assert_eq!(decoded.unwrap().unwrap(), r#"{"version":3}"#);
+
+        assert!(decode_data_url("data:application/json;base64,not*base64").unwrap().is_err());
+        assert!(decode_data_url("main.js.map").is_none());
+    }
+}
@@ -8,7 +8,7 @@ pub struct MappingEntry {
     pub gen_column: ResolvedOffset,
     pub source: Option<SourceEntry>,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SourceEntry {
     pub source_idx: ResolvedOffset,
     pub source_line: ResolvedOffset,
@@ -18,6 +18,7 @@ pub struct SourceEntry {
 
 pub struct MappingData<'a> {
     internal_iterator: OffsetIterator<'a>,
+    gen_line: ResolvedOffset,
     gen_column: ResolvedOffset,
     source_idx: ResolvedOffset,
     source_line: ResolvedOffset,
@@ -72,6 +73,7 @@ impl<'a> MappingData<'a> {
                 encountered: Encountered::Semicolon,
                 line: 0,
             },
+            gen_line: 0,
             gen_column: 0,
             source_idx: 0,
             source_line: 0,
@@ -93,6 +95,11 @@ impl<'a> Iterator for MappingData<'a> {
                 Encountered::End => None,
             },
             Ok(gen_col_offset) => {
+                // The generated column starts over on every line, unlike the other fields
+                if self.gen_line != self.internal_iterator.line {
+                    self.gen_line = self.internal_iterator.line;
+                    self.gen_column = 0;
+                }
                 {
                     self.gen_column = self.gen_column + gen_col_offset;
                 }
@@ -195,4 +202,13 @@ mod test {
             println!("{:?}", v);
         }
     }
+
+    #[test]
+    fn test_generated_column_resets_per_line() {
+        let columns = MappingData::new(b"CAAA,EAAC;AAAA;;GAAA")
+            .map(|v| (v.gen_line, v.gen_column))
+            .collect::<Vec<_>>();
+
+        assert_eq!(columns, vec![(0, 1), (0, 3), (1, 0), (3, 3)]);
+    }
 }
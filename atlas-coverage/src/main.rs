@@ -13,10 +13,14 @@ use std::io::BufWriter;
 #[derive(StructOpt, Debug)]
 #[structopt(name = "atlas-coverage")]
 struct Opt {
-    /// Where to write the xml
+    /// Where to write the coverage report. For the html format, this is a directory
     #[structopt(short = "o", long = "output", parse(from_os_str))]
     output: PathBuf,
 
+    /// Output format: sonar, lcov, cobertura, istanbul or html
+    #[structopt(short = "f", long = "format", default_value = "sonar")]
+    format: e2e_cc::OutputFormat,
+
     /// Path to configuration json. Uses the CWD if omitted
     #[structopt(short = "-c", long = "config", parse(from_os_str))]
     config: Option<PathBuf>,
@@ -35,16 +39,6 @@ fn main() -> Result<(), Box<dyn Error>>{
         e2e_cc::settings::from_root()
     }.expect("Cannot read settings");
 
-    let writer = {
-        let output_file = opt.output;
-
-        fs::create_dir_all(output_file.parent().unwrap())?;
-
-        let unbuffered = OpenOptions::new().create(true).write(true).truncate(true).open(output_file).expect("Cannot open output file");
-
-        BufWriter::new(unbuffered)
-    };
-
     let inputs : Vec<_> = {
         let input_directory_items = fs::read_dir(opt.input)?;
 
@@ -61,5 +55,19 @@ fn main() -> Result<(), Box<dyn Error>>{
             }).collect()
     };
 
-    Ok(e2e_cc::run(settings, inputs, Some(writer)))
+    if opt.format == e2e_cc::OutputFormat::Html {
+        return Ok(e2e_cc::run_html(settings, inputs, &opt.output)?);
+    }
+
+    let writer = {
+        let output_file = opt.output;
+
+        fs::create_dir_all(output_file.parent().unwrap())?;
+
+        let unbuffered = OpenOptions::new().create(true).write(true).truncate(true).open(output_file).expect("Cannot open output file");
+
+        BufWriter::new(unbuffered)
+    };
+
+    Ok(e2e_cc::run(settings, inputs, opt.format, Some(writer))?)
 }
\ No newline at end of file